@@ -0,0 +1,65 @@
+use current::graphics::Frame;
+use current::sprite::{BlendMode, Sprite, Transform};
+use current::*;
+use glam::Vec2;
+use wgpu::Color;
+
+fn main() {
+    Blend::run();
+}
+
+struct Blend {
+    back: Sprite,
+    rects: Vec<Sprite>,
+}
+
+impl Game for Blend {
+    fn init(data: &mut GameData) -> Self {
+        data.set_window_size((800, 300).into());
+        data.graphics.background_color = Color {
+            r: 0.2,
+            g: 0.2,
+            b: 0.2,
+            a: 1.0,
+        };
+
+        let color = Color {
+            r: 1.0,
+            g: 0.5,
+            b: 0.0,
+            a: 0.75,
+        };
+        let rects = [
+            BlendMode::Alpha,
+            BlendMode::Additive,
+            BlendMode::Multiply,
+            BlendMode::Screen,
+            BlendMode::Premultiplied,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, blend_mode)| {
+            Sprite::new_color_rect(data.graphics, color)
+                .with_transform(Transform {
+                    translation: (i as f32 * 150.0 - 300.0, 0.0, 1.0).into(),
+                    scale: Vec2::new(100.0, 100.0),
+                    ..Default::default()
+                })
+                .with_blend_mode(blend_mode)
+        })
+        .collect();
+
+        Self {
+            back: Sprite::new_color_rect(data.graphics, Color::BLUE)
+                .with_transform(Transform::scale(Vec2::new(800.0, 50.0))),
+            rects,
+        }
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        self.back.render_to(&mut frame);
+        for rect in &self.rects {
+            rect.render_to(&mut frame);
+        }
+    }
+}
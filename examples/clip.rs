@@ -0,0 +1,56 @@
+use current::graphics::Frame;
+use current::rect::Rect;
+use current::sprite::{Filter, Sprite, Transform};
+use current::*;
+use glam::Vec2;
+use wgpu::Color;
+
+fn main() {
+    Clip::run();
+}
+
+struct Clip {
+    image: Sprite,
+    mask: Sprite,
+    back: Sprite,
+    angle: f32,
+}
+
+impl Game for Clip {
+    fn init(data: &mut GameData) -> Self {
+        data.set_window_size((600, 300).into());
+        Self {
+            image: Sprite::new_path_rect(data.graphics, "examples/test.png", Filter::Nearest)
+                .with_transform(Transform::scale(Vec2::new(256.0, 256.0))),
+            mask: Sprite::new_color_rect(data.graphics, Color::WHITE),
+            back: Sprite::new_color_rect(data.graphics, Color::GREEN)
+                .with_transform(Transform::scale(Vec2::new(600.0, 300.0))),
+            angle: 0.0,
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        self.angle += data.delta_time.as_secs_f32();
+        self.mask.set_transform(
+            Transform::scale(Vec2::new(160.0, 160.0))
+                .with_translation((150.0, 0.0, 0.0).into())
+                .with_straight_rotation(self.angle),
+        );
+        self.image.modify_transform(|transform| {
+            transform.with_translation((150.0 * self.angle.sin(), 0.0, 0.0).into())
+        });
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        // Only the left half of the image is visible through the clip rect, and only
+        // the part under the spinning square on the right.
+        frame.push_clip_rect(Rect::new(Vec2::new(-300.0, -150.0), Vec2::new(0.0, 150.0)));
+        self.back.render_to(&mut frame);
+        self.image.render_to(&mut frame);
+        frame.pop_clip_rect();
+
+        frame.push_mask(&self.mask);
+        self.image.render_to(&mut frame);
+        frame.pop_mask();
+    }
+}
@@ -1,9 +1,11 @@
 use std::f32::consts::TAU;
 
-use current::graphics::{FontID, Frame, Graphics};
+use current::graphics::Frame;
 use current::input::InputState;
+use current::layer::UI_LAYER;
 use current::random::Noise;
 use current::sprite::{Filter, Sprite, Transform};
+use current::text::{Text, TextStyle};
 use current::{Game, GameData, GameExt};
 use glam::{IVec2, Vec2};
 use wgpu::Color;
@@ -20,8 +22,7 @@ struct Crawl {
     point_pos: IVec2,
     point_sprite: Sprite,
 
-    font: FontID,
-    points_text: Sprite,
+    points_text: Text,
     points: u32,
     noise: Noise,
 }
@@ -76,8 +77,13 @@ impl Game for Crawl {
             )
             .with_transform(position_transform(point_pos, Direction::Up)),
 
-            font,
-            points_text: make_text(data.graphics, font, 0),
+            points_text: Text::new(
+                data.graphics,
+                font,
+                "Points: 0",
+                TextStyle::new(72.0, Color::WHITE),
+            )
+            .with_layer(UI_LAYER),
             points: 0,
             noise: Noise::new(),
         }
@@ -109,6 +115,7 @@ impl Game for Crawl {
         if modified {
             self.player_sprite
                 .set_transform(position_transform(self.player_pos, self.player_direction));
+            data.graphics.camera.position = self.player_pos.as_vec2() * 32.0;
             if self.player_pos == self.point_pos {
                 self.points += 1;
                 self.point_pos = IVec2::new(
@@ -117,9 +124,11 @@ impl Game for Crawl {
                 );
                 self.point_sprite
                     .set_transform(position_transform(self.point_pos, Direction::Up));
-                self.points_text = make_text(data.graphics, self.font, self.points);
+                self.points_text
+                    .set_text(data.graphics, &format!("Points: {}", self.points));
             }
         }
+        self.points_text.refresh(data.graphics);
     }
 
     fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
@@ -137,15 +146,3 @@ fn position_transform(pos: IVec2, direction: Direction) -> Transform {
     }
     .with_straight_rotation(direction.into())
 }
-
-fn make_text(graphics: &mut Graphics, font: FontID, points: u32) -> Sprite {
-    Sprite::new_text_rect(
-        graphics,
-        font,
-        &format!("Points: {}", points),
-        72,
-        Color::WHITE,
-        Filter::Linear,
-    )
-    .with_modified_transform(|transform| transform.with_z(1.0))
-}
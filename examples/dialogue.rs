@@ -0,0 +1,63 @@
+use current::graphics::Frame;
+use current::input::InputState;
+use current::sprite::{Filter, Sprite, Transform};
+use current::text::{Markup, Text, TextStyle};
+use current::*;
+use glam::Vec2;
+use wgpu::Color;
+
+fn main() {
+    Dialogue::run();
+}
+
+struct Dialogue {
+    back: Sprite,
+    text: Text,
+    time: f32,
+}
+
+impl Game for Dialogue {
+    fn init(data: &mut GameData) -> Self {
+        data.set_window_size((800, 300).into());
+        let font = data
+            .graphics
+            .load_font("examples/LiberationSans-Regular.ttf");
+        let coin = data.graphics.texture_manager.make_texture(
+            &data.graphics.device,
+            &data.graphics.queue,
+            image::open("examples/test.png").unwrap(),
+            Filter::Linear,
+        );
+
+        Self {
+            back: Sprite::new_color_rect(data.graphics, Color::BLACK)
+                .with_transform(Transform::scale(Vec2::new(760.0, 260.0))),
+            text: Text::new_markup(
+                data.graphics,
+                font,
+                "Welcome, [color=yellow]traveller[/color]! That will be [b]50[/b] [img=coin] \
+                for the [color=#40c0ff][size=40]magic sword[/size][/color].\n\
+                Press space to hear it again.",
+                TextStyle::new(32.0, Color::WHITE).with_max_width(720.0),
+                Markup::new().with_icon("coin", coin),
+            )
+            .with_transform(Transform::default().with_translation((-360.0, 110.0, 1.0).into())),
+            time: 0.0,
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        if data.input.is_key(57, InputState::Pressed) {
+            self.time = 0.0;
+        }
+        self.time += data.delta_time.as_secs_f32();
+        self.text
+            .set_visible_characters(Some((self.time * 30.0) as usize));
+        self.text.refresh(data.graphics);
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        self.back.render_to(&mut frame);
+        self.text.render_to(&mut frame);
+    }
+}
@@ -0,0 +1,104 @@
+use std::f32::consts::TAU;
+
+use current::gradient::Gradient;
+use current::graphics::Frame;
+use current::sprite::{Filter, Sprite, Transform};
+use current::*;
+
+use glam::{Vec2, Vec3};
+use wgpu::Color;
+
+fn main() {
+    Gradients::run();
+}
+
+struct Gradients {
+    sky: Sprite,
+    sun: Sprite,
+    tinted: Sprite,
+}
+
+impl Game for Gradients {
+    fn init(data: &mut GameData) -> Self {
+        data.graphics.frame_size = Some((640.0, 480.0).into());
+
+        let sky = Gradient::linear(
+            Vec2::new(0.0, -0.5),
+            Vec2::new(0.0, 0.5),
+            Color {
+                r: 1.0,
+                g: 0.6,
+                b: 0.3,
+                a: 1.0,
+            },
+            Color {
+                r: 0.1,
+                g: 0.1,
+                b: 0.4,
+                a: 1.0,
+            },
+        )
+        .with_stop(
+            0.3,
+            Color {
+                r: 0.9,
+                g: 0.4,
+                b: 0.5,
+                a: 1.0,
+            },
+        );
+
+        // The sun fades out towards its edge, so it is drawn as a translucent circle.
+        let sun = Gradient::radial(
+            Vec2::ZERO,
+            0.5,
+            Color::WHITE,
+            Color {
+                r: 1.0,
+                g: 0.8,
+                b: 0.2,
+                a: 0.0,
+            },
+        );
+        let circle: Vec<_> = (0..48)
+            .map(|i| {
+                let angle = i as f32 / 48.0 * TAU;
+                Vec2::new(angle.cos(), angle.sin()) * 0.5
+            })
+            .collect();
+
+        let texture = data.graphics.texture_manager.make_texture(
+            &data.graphics.device,
+            &data.graphics.queue,
+            image::open("examples/test.png").unwrap(),
+            Filter::Linear,
+        );
+        let tint = Gradient::linear(
+            Vec2::new(-0.5, 0.0),
+            Vec2::new(0.5, 0.0),
+            Color::RED,
+            Color::BLUE,
+        );
+
+        Self {
+            sky: Sprite::new_gradient_rect(data.graphics, &sky).with_transform(
+                Transform::scale(Vec2::new(640.0, 480.0))
+                    .with_translation(Vec3::new(0.0, 0.0, -1.0)),
+            ),
+            sun: Sprite::new_gradient_shape(data.graphics, &circle, &sun).with_transform(
+                Transform::scale(Vec2::splat(160.0)).with_translation(Vec3::new(-150.0, 60.0, 0.0)),
+            ),
+            tinted: Sprite::new_texture_gradient_rect(data.graphics, texture, &tint)
+                .with_transform(
+                    Transform::scale(Vec2::splat(160.0))
+                        .with_translation(Vec3::new(150.0, -60.0, 0.0)),
+                ),
+        }
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        self.sky.render_to(&mut frame);
+        self.sun.render_to(&mut frame);
+        self.tinted.render_to(&mut frame);
+    }
+}
@@ -1,5 +1,5 @@
 use current::graphics::Frame;
-use current::sprite::{Filter, Sprite, Transform};
+use current::sprite::{Filter, SamplerDesc, Sprite, Transform};
 use current::*;
 
 fn main() {
@@ -15,18 +15,35 @@ impl Game for Image {
     fn init(data: &mut GameData) -> Self {
         data.graphics.frame_size = Some((2.0, 2.0).into());
 
+        // A texture that has been removed is drawn as the error texture.
+        let removed = data
+            .graphics
+            .texture_manager
+            .make_texture_from_bytes(
+                &data.graphics.device,
+                &data.graphics.queue,
+                include_bytes!("test.png"),
+                Filter::Linear,
+            )
+            .unwrap();
+        data.graphics.texture_manager.remove(removed);
+
         Self {
-            rect: Sprite::new_texture_rect(data.graphics, 5).with_transform(Transform {
+            rect: Sprite::new_texture_rect(data.graphics, removed).with_transform(Transform {
                 translation: (-0.5, 0.0, 0.0).into(),
                 scale: (1.0, 2.0).into(),
                 ..Default::default()
             }),
-            test: Sprite::new_path_rect(data.graphics, "examples/test.png", Filter::Linear)
-                .with_transform(Transform {
-                    translation: (0.5, 0.0, 0.0).into(),
-                    scale: (1.0, 2.0).into(),
-                    ..Default::default()
-                }),
+            test: Sprite::new_path_rect(
+                data.graphics,
+                "examples/test.png",
+                SamplerDesc::trilinear().with_anisotropy(16),
+            )
+            .with_transform(Transform {
+                translation: (0.5, 0.0, 0.0).into(),
+                scale: (1.0, 2.0).into(),
+                ..Default::default()
+            }),
         }
     }
 
@@ -0,0 +1,134 @@
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_8, TAU};
+
+use current::graphics::Frame;
+use current::layer::WORLD_LAYER;
+use current::light::{Light, Occluder};
+use current::sprite::{Filter, Sprite, Transform};
+use current::*;
+
+use glam::{Vec2, Vec3};
+use image::{DynamicImage, Rgba, RgbaImage};
+use wgpu::Color;
+
+fn main() {
+    Lighting::run();
+}
+
+struct Lighting {
+    tiles: Vec<Sprite>,
+    boxes: Vec<(Sprite, Occluder)>,
+    mouse: Vec2,
+    elapsed: f32,
+}
+
+/// A normal map of round bumps, one in each quarter of the texture.
+fn bumps() -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(128, 128, |x, y| {
+        let offset = (Vec2::new((x % 64) as f32, (y % 64) as f32) - 31.5) / 30.0;
+        // Image rows go down while the world goes up.
+        let normal = match offset.length_squared() < 1.0 {
+            true => Vec3::new(offset.x, -offset.y, (1.0 - offset.length_squared()).sqrt()),
+            false => Vec3::Z,
+        };
+        let encoded = (normal * 0.5 + 0.5) * 255.0;
+        Rgba([encoded.x as u8, encoded.y as u8, encoded.z as u8, 255])
+    }))
+}
+
+impl Game for Lighting {
+    fn init(data: &mut GameData) -> Self {
+        data.graphics.frame_size = Some((640.0, 480.0).into());
+        data.graphics.layers[WORLD_LAYER].lighting = Some(Color {
+            r: 0.05,
+            g: 0.05,
+            b: 0.1,
+            a: 1.0,
+        });
+
+        let texture = data.graphics.texture_manager.make_texture(
+            &data.graphics.device,
+            &data.graphics.queue,
+            image::open("examples/test.png").unwrap(),
+            Filter::Linear,
+        );
+        let normals = data.graphics.texture_manager.make_linear_texture(
+            &data.graphics.device,
+            &data.graphics.queue,
+            bumps(),
+            Filter::Linear,
+        );
+
+        let mut tiles = Vec::new();
+        for x in -5..5 {
+            for y in -4..4 {
+                tiles.push(
+                    Sprite::new_texture_rect(data.graphics, texture)
+                        .with_normal_map(normals)
+                        .with_transform(Transform::scale(Vec2::splat(64.0)).with_translation(
+                            Vec3::new(x as f32 * 64.0 + 32.0, y as f32 * 64.0 + 32.0, 0.0),
+                        )),
+                );
+            }
+        }
+
+        let boxes = [Vec2::new(-120.0, 60.0), Vec2::new(140.0, -80.0)]
+            .into_iter()
+            .map(|position| {
+                let size = Vec2::splat(50.0);
+                let sprite = Sprite::new_color_rect(data.graphics, Color::BLACK)
+                    .with_transform(Transform::scale(size).with_translation(position.extend(1.0)));
+                (sprite, Occluder::rect(position, size))
+            })
+            .collect();
+
+        Self {
+            tiles,
+            boxes,
+            mouse: Vec2::ZERO,
+            elapsed: 0.0,
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        self.mouse = data.input.frame_mouse_pos;
+        self.elapsed += data.delta_time.as_secs_f32();
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        for tile in &self.tiles {
+            tile.render_to(&mut frame);
+        }
+        for (sprite, occluder) in &self.boxes {
+            sprite.render_to(&mut frame);
+            occluder.render_to(&mut frame);
+        }
+
+        // A warm light follows the mouse, while a blue spot light sweeps around.
+        Light::point(
+            self.mouse,
+            300.0,
+            Color {
+                r: 1.0,
+                g: 0.8,
+                b: 0.5,
+                a: 1.0,
+            },
+        )
+        .with_intensity(1.5)
+        .render_to(&mut frame);
+        Light::spot(
+            Vec2::new(0.0, 200.0),
+            500.0,
+            Color {
+                r: 0.4,
+                g: 0.6,
+                b: 1.0,
+                a: 1.0,
+            },
+            -FRAC_PI_2 + (self.elapsed * TAU / 6.0).sin(),
+            FRAC_PI_8,
+        )
+        .with_falloff(1.0)
+        .render_to(&mut frame);
+    }
+}
@@ -0,0 +1,68 @@
+use current::graphics::{Frame, TextureID};
+use current::input::InputState;
+use current::sprite::{Filter, Sprite, Transform};
+use current::*;
+
+use glam::Vec2;
+use image::{DynamicImage, Rgba, RgbaImage};
+use winit::event::MouseButton;
+
+const SIZE: u32 = 64;
+
+fn main() {
+    Paint::run();
+}
+
+struct Paint {
+    canvas: TextureID,
+    sprite: Sprite,
+}
+
+impl Game for Paint {
+    fn init(data: &mut GameData) -> Self {
+        data.graphics.frame_size = Some(Vec2::splat(SIZE as f32));
+
+        let canvas = data.graphics.texture_manager.make_texture(
+            &data.graphics.device,
+            &data.graphics.queue,
+            DynamicImage::ImageRgba8(blank()),
+            Filter::Nearest,
+        );
+        Self {
+            canvas,
+            sprite: Sprite::new_texture_rect(data.graphics, canvas)
+                .with_transform(Transform::scale(Vec2::splat(SIZE as f32))),
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        // Paint a 3x3 brush under the mouse while the left button is held.
+        if data.input.is_button(MouseButton::Left, InputState::Down) {
+            let pixel = Vec2::new(data.input.frame_mouse_pos.x, -data.input.frame_mouse_pos.y)
+                + SIZE as f32 / 2.0;
+            let brush = RgbaImage::from_pixel(3, 3, Rgba([255, 64, 64, 255]));
+            data.graphics.texture_manager.update_region(
+                &data.graphics.queue,
+                self.canvas,
+                (pixel.x as u32).saturating_sub(1),
+                (pixel.y as u32).saturating_sub(1),
+                &brush,
+            );
+        }
+
+        // Press C to clear the canvas.
+        if data.input.is_key(46, InputState::Pressed) {
+            data.graphics
+                .texture_manager
+                .update(&data.graphics.queue, self.canvas, &blank());
+        }
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        self.sprite.render_to(&mut frame);
+    }
+}
+
+fn blank() -> RgbaImage {
+    RgbaImage::from_pixel(SIZE, SIZE, Rgba([255, 255, 255, 255]))
+}
@@ -0,0 +1,65 @@
+use current::graphics::Frame;
+use current::input::InputState;
+use current::parallax::ParallaxLayer;
+use current::sprite::{Filter, Sprite, Transform};
+use current::*;
+
+use glam::Vec2;
+use wgpu::Color;
+
+fn main() {
+    Parallax::run();
+}
+
+struct Parallax {
+    far: ParallaxLayer,
+    near: ParallaxLayer,
+    player: Sprite,
+}
+
+impl Game for Parallax {
+    fn init(data: &mut GameData) -> Self {
+        data.graphics.frame_size = Some((640.0, 480.0).into());
+
+        Self {
+            // The far layer tiles in both directions and barely moves, while the near
+            // layer only tiles horizontally.
+            far: ParallaxLayer::new_path(
+                data.graphics,
+                "examples/test.png",
+                Filter::Linear,
+                Vec2::splat(128.0),
+            )
+            .with_scroll_factor(Vec2::splat(0.2))
+            .with_repeat(true, true)
+            .with_z(-1.0),
+            near: ParallaxLayer::new_path(
+                data.graphics,
+                "examples/test.png",
+                Filter::Linear,
+                Vec2::new(96.0, 64.0),
+            )
+            .with_scroll_factor(Vec2::new(0.6, 1.0))
+            .with_offset(Vec2::new(0.0, -160.0)),
+            player: Sprite::new_color_rect(data.graphics, Color::RED)
+                .with_transform(Transform::scale(Vec2::splat(32.0))),
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        // Move with A and D, or W and S.
+        let speed = 200.0 * data.delta_time.as_secs_f32();
+        for (key, direction) in [(30, -Vec2::X), (32, Vec2::X), (17, Vec2::Y), (31, -Vec2::Y)] {
+            if data.input.is_key(key, InputState::Down) {
+                data.graphics.camera.position += direction * speed;
+            }
+        }
+        self.player.transform.translation = data.graphics.camera.position.extend(0.0);
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        self.far.render_to(&mut frame);
+        self.near.render_to(&mut frame);
+        self.player.render_to(&mut frame);
+    }
+}
@@ -0,0 +1,82 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use current::graphics::Frame;
+use current::input::InputState;
+use current::particles::{Curve, EmissionShape, ParticleEmitter};
+use current::sprite::BlendMode;
+use current::*;
+
+use glam::{Vec2, Vec3};
+use wgpu::Color;
+
+fn main() {
+    Particles::run();
+}
+
+struct Particles {
+    fire: ParticleEmitter,
+    sparks: ParticleEmitter,
+}
+
+impl Game for Particles {
+    fn init(data: &mut GameData) -> Self {
+        data.graphics.frame_size = Some((640.0, 480.0).into());
+
+        let orange = Color {
+            r: 1.0,
+            g: 0.5,
+            b: 0.1,
+            a: 1.0,
+        };
+        let smoke = Color {
+            r: 0.3,
+            g: 0.3,
+            b: 0.3,
+            a: 0.0,
+        };
+
+        Self {
+            // Fire rises from a line, turning from yellow to fading smoke as it grows.
+            fire: ParticleEmitter::new(data.graphics)
+                .with_seed(1)
+                .with_position(Vec3::new(0.0, -150.0, 0.0))
+                .with_rate(200.0)
+                .with_shape(EmissionShape::Box {
+                    size: Vec2::new(80.0, 10.0),
+                })
+                .with_lifetime(0.8..1.6)
+                .with_cone(FRAC_PI_2, 0.3)
+                .with_speed(60.0..140.0)
+                .with_drag(0.5)
+                .with_size(Curve::new(Vec2::splat(6.0), Vec2::splat(24.0)))
+                .with_color(Curve::new(Color::WHITE, smoke).with_key(0.3, orange))
+                .with_rotation(0.0..PI, Curve::new(0.0, PI))
+                .with_blend_mode(BlendMode::Additive),
+            // Press space for a burst of sparks that fall back down.
+            sparks: ParticleEmitter::new(data.graphics)
+                .with_seed(2)
+                .with_emitting(false)
+                .with_position(Vec3::new(0.0, 50.0, 1.0))
+                .with_shape(EmissionShape::Circle { radius: 10.0 })
+                .with_lifetime(1.0..2.0)
+                .with_cone(0.0, PI)
+                .with_speed(100.0..300.0)
+                .with_gravity(Vec2::new(0.0, -300.0))
+                .with_size(Curve::new(Vec2::splat(4.0), Vec2::ZERO))
+                .with_color(Curve::constant(orange)),
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        if data.input.is_key(57, InputState::Pressed) {
+            self.sparks.burst(200);
+        }
+        self.fire.update(data.delta_time);
+        self.sparks.update(data.delta_time);
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        self.fire.render_to(&mut frame);
+        self.sparks.render_to(&mut frame);
+    }
+}
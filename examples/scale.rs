@@ -0,0 +1,54 @@
+use current::graphics::Frame;
+use current::input::InputState;
+use current::scale::ScaleMode;
+use current::sprite::{Filter, Sprite, Transform};
+use current::*;
+use glam::Vec2;
+use wgpu::Color;
+
+fn main() {
+    Scale::run();
+}
+
+struct Scale {
+    image: Sprite,
+    cursor: Sprite,
+}
+
+impl Game for Scale {
+    fn init(data: &mut GameData) -> Self {
+        data.set_resizable(true);
+        data.graphics.frame_size = Some(Vec2::new(160.0, 90.0));
+        data.graphics.scale_mode = ScaleMode::PixelPerfect;
+        data.graphics.background_color = Color::BLUE;
+
+        Self {
+            image: Sprite::new_path_rect(data.graphics, "examples/test.png", Filter::Nearest)
+                .with_transform(Transform::scale(Vec2::new(64.0, 64.0))),
+            cursor: Sprite::new_color_rect(data.graphics, Color::RED),
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        // Space switches between the scaling modes.
+        if data.input.is_key(57, InputState::Pressed) {
+            data.graphics.scale_mode = match data.graphics.scale_mode {
+                ScaleMode::Stretch => ScaleMode::Letterbox,
+                ScaleMode::Letterbox => ScaleMode::PixelPerfect,
+                ScaleMode::PixelPerfect => ScaleMode::Expand,
+                ScaleMode::Expand => ScaleMode::Stretch,
+            };
+            println!("{:?}", data.graphics.scale_mode);
+        }
+
+        self.cursor.set_transform(
+            Transform::scale(Vec2::new(4.0, 4.0))
+                .with_translation(data.input.frame_mouse_pos.extend(1.0)),
+        );
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        self.image.render_to(&mut frame);
+        self.cursor.render_to(&mut frame);
+    }
+}
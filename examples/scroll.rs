@@ -0,0 +1,53 @@
+use current::graphics::Frame;
+use current::sprite::{SamplerDesc, Sprite, Transform};
+use current::*;
+
+use glam::Vec2;
+use wgpu::AddressMode;
+
+fn main() {
+    Scroll::run();
+}
+
+struct Scroll {
+    repeat: Sprite,
+    mirror: Sprite,
+}
+
+impl Game for Scroll {
+    fn init(data: &mut GameData) -> Self {
+        data.graphics.frame_size = Some((2.0, 2.0).into());
+
+        // Both quads tile the texture four times and scroll it, one repeating it and
+        // the other mirroring every other copy.
+        let tiled = |data: &mut GameData, address_mode, x| {
+            Sprite::new_path_rect(
+                data.graphics,
+                "examples/test.png",
+                SamplerDesc::default().with_address_mode(address_mode),
+            )
+            .with_transform(Transform {
+                translation: (x, 0.0, 0.0).into(),
+                scale: (1.0, 2.0).into(),
+                ..Default::default()
+            })
+            .with_uv_scale(Vec2::new(2.0, 4.0))
+            .with_uv_scroll(Vec2::new(0.25, 0.1))
+        };
+
+        Self {
+            repeat: tiled(data, AddressMode::Repeat, -0.5),
+            mirror: tiled(data, AddressMode::MirrorRepeat, 0.5),
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        self.repeat.scroll_uv(data.delta_time);
+        self.mirror.scroll_uv(data.delta_time);
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        self.repeat.render_to(&mut frame);
+        self.mirror.render_to(&mut frame);
+    }
+}
@@ -0,0 +1,62 @@
+use current::camera::{Camera, Viewport};
+use current::graphics::Frame;
+use current::input::InputState;
+use current::sprite::{Filter, Sprite, Transform};
+use current::*;
+use glam::Vec2;
+use wgpu::Color;
+
+fn main() {
+    Split::run();
+}
+
+struct Split {
+    ground: Sprite,
+    players: [Sprite; 2],
+    positions: [Vec2; 2],
+}
+
+impl Game for Split {
+    fn init(data: &mut GameData) -> Self {
+        data.set_window_size((800, 400).into());
+        data.graphics.frame_size = Some(Vec2::new(400.0, 200.0));
+
+        Self {
+            ground: Sprite::new_path_rect(data.graphics, "examples/test.png", Filter::Nearest)
+                .with_transform(Transform::scale(Vec2::new(600.0, 600.0))),
+            players: [
+                Sprite::new_color_rect(data.graphics, Color::RED),
+                Sprite::new_color_rect(data.graphics, Color::BLUE),
+            ],
+            positions: [Vec2::new(-50.0, 0.0), Vec2::new(50.0, 0.0)],
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        // WASD moves the first player and IJKL moves the second.
+        let controls = [[17, 30, 31, 32], [23, 36, 37, 38]];
+        let speed = 100.0 * data.delta_time.as_secs_f32();
+        for (position, keys) in self.positions.iter_mut().zip(controls) {
+            let directions = [Vec2::Y, -Vec2::X, -Vec2::Y, Vec2::X];
+            for (key, direction) in keys.into_iter().zip(directions) {
+                if data.input.is_key(key, InputState::Down) {
+                    *position += direction * speed;
+                }
+            }
+        }
+
+        for (player, position) in self.players.iter_mut().zip(self.positions) {
+            player.set_transform(
+                Transform::scale(Vec2::new(16.0, 16.0)).with_translation(position.extend(1.0)),
+            );
+        }
+        data.graphics.viewports = Viewport::split(&self.positions.map(Camera::new));
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        self.ground.render_to(&mut frame);
+        for player in &self.players {
+            player.render_to(&mut frame);
+        }
+    }
+}
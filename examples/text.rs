@@ -1,7 +1,9 @@
 use current::graphics::FontID;
 use current::sprite::Sprite;
+use current::text::{Align, SdfStyle, Text, TextStyle};
 use current::*;
 
+use glam::{Vec2, Vec3};
 use wgpu::Color;
 
 fn main() {
@@ -9,19 +11,18 @@ fn main() {
 }
 
 struct TextDemo {
-    font: FontID,
     sprite: Sprite,
-    text: String,
+    text: Text,
+    outlined: Text,
 }
 
 impl Game for TextDemo {
     fn init(data: &mut GameData) -> Self {
         data.graphics.frame_size = Some((1000.0, 1000.0).into());
-        let font = data
+        let font: FontID = data
             .graphics
             .load_font("examples/LiberationSans-Regular.ttf");
         Self {
-            font,
             sprite: Sprite::new_text_rect(
                 data.graphics,
                 font,
@@ -30,25 +31,48 @@ impl Game for TextDemo {
                 Color::GREEN,
                 sprite::Filter::Linear,
             ),
-            text: "Text".to_owned(),
+            text: Text::new(
+                data.graphics,
+                font,
+                "Press S to add more text.",
+                TextStyle::new(48.0, Color::WHITE)
+                    .with_max_width(800.0)
+                    .with_align(Align::Justify),
+            )
+            .with_transform(
+                sprite::Transform::default().with_translation(Vec3::new(-400.0, -150.0, 0.0)),
+            ),
+            outlined: Text::new(
+                data.graphics,
+                font,
+                "Outlined",
+                TextStyle::new(24.0, Color::WHITE).with_sdf(
+                    SdfStyle::new()
+                        .with_outline(1.5, Color::BLACK)
+                        .with_shadow(Vec2::new(1.5, -1.5), 1.0, Color::BLACK)
+                        .with_glow(2.0, Color::BLUE),
+                ),
+            )
+            .with_transform(
+                sprite::Transform::default()
+                    .with_translation(Vec3::new(-400.0, 400.0, 0.0))
+                    .with_scale(Vec2::splat(4.0)),
+            ),
         }
     }
 
     fn update(&mut self, data: &mut GameData) {
         if data.input.is_key(31, input::InputState::Pressed) {
-            self.text.push('#');
-            self.sprite = Sprite::new_text_rect(
-                data.graphics,
-                self.font,
-                &self.text,
-                256,
-                Color::WHITE,
-                sprite::Filter::Linear,
-            )
+            let text = format!("{} The quick brown fox.", self.text.text());
+            self.text.set_text(data.graphics, &text);
         }
+        self.text.refresh(data.graphics);
+        self.outlined.refresh(data.graphics);
     }
 
     fn render<'a>(&'a mut self, mut frame: graphics::Frame<'a>) {
         self.sprite.render_to(&mut frame);
+        self.text.render_to(&mut frame);
+        self.outlined.render_to(&mut frame);
     }
 }
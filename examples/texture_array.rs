@@ -0,0 +1,69 @@
+use current::graphics::Frame;
+use current::sprite::{Filter, Sprite, Transform};
+use current::*;
+
+use glam::{Vec2, Vec3};
+
+fn main() {
+    TextureArray::run();
+}
+
+struct TextureArray {
+    sprites: Vec<Sprite>,
+    elapsed: f32,
+}
+
+impl Game for TextureArray {
+    fn init(data: &mut GameData) -> Self {
+        data.graphics.frame_size = Some((640.0, 480.0).into());
+
+        let image = image::open("examples/test.png").unwrap();
+        let images = vec![
+            image.clone(),
+            image.fliph(),
+            image.flipv(),
+            image.rotate180(),
+        ];
+        let array = data.graphics.texture_manager.make_texture_array(
+            &data.graphics.device,
+            &data.graphics.queue,
+            images,
+            Filter::Linear,
+        );
+
+        // Every sprite uses the same array, so the whole grid is drawn in one call even
+        // though neighbouring sprites show different layers.
+        let mut sprites = Vec::new();
+        for y in 0..8 {
+            for x in 0..10 {
+                let position = Vec2::new(x as f32 - 4.5, y as f32 - 3.5) * 60.0;
+                sprites.push(
+                    Sprite::new_array_rect(data.graphics, array, (x + y) % 4).with_transform(
+                        Transform::scale(Vec2::splat(50.0))
+                            .with_translation(Vec3::new(position.x, position.y, 0.0)),
+                    ),
+                );
+            }
+        }
+
+        Self {
+            sprites,
+            elapsed: 0.0,
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        // Cycle every sprite through the layers once a second.
+        self.elapsed += data.delta_time.as_secs_f32();
+        let step = (self.elapsed * 4.0) as u32;
+        for (i, sprite) in self.sprites.iter_mut().enumerate() {
+            sprite.set_array_layer((i as u32 + step) % 4);
+        }
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        for sprite in &self.sprites {
+            sprite.render_to(&mut frame);
+        }
+    }
+}
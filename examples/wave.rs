@@ -0,0 +1,68 @@
+use current::graphics::Frame;
+use current::sprite::{ColorVertex, Sprite, Transform};
+use current::*;
+
+use glam::Vec2;
+
+/// The number of vertices along each side of the grid, which has more vertices than
+/// `u16` indices can reach.
+const SIZE: u32 = 300;
+
+fn main() {
+    Wave::run();
+}
+
+struct Wave {
+    grid: Sprite,
+    elapsed: f32,
+}
+
+impl Game for Wave {
+    fn init(data: &mut GameData) -> Self {
+        data.graphics.frame_size = Some((2.0, 2.0).into());
+
+        let mut indices = Vec::new();
+        for y in 0..SIZE - 1 {
+            for x in 0..SIZE - 1 {
+                let i = y * SIZE + x;
+                indices.extend([i, i + 1, i + SIZE + 1, i, i + SIZE + 1, i + SIZE]);
+            }
+        }
+
+        let vertices = grid(0.0);
+        Self {
+            grid: Sprite::new_color_mesh(data.graphics, &vertices, &indices)
+                .with_transform(Transform::scale(Vec2::splat(1.8))),
+            elapsed: 0.0,
+        }
+    }
+
+    fn update(&mut self, data: &mut GameData) {
+        // Only the vertices move, so the indices are left as they are.
+        self.elapsed += data.delta_time.as_secs_f32();
+        self.grid
+            .update_vertices(data.graphics, &grid(self.elapsed));
+    }
+
+    fn render<'a>(&'a mut self, mut frame: Frame<'a>) {
+        self.grid.render_to(&mut frame);
+    }
+}
+
+/// A grid covering the unit square, shaded and pushed around by ripples.
+fn grid(time: f32) -> Vec<ColorVertex> {
+    let mut vertices = Vec::with_capacity((SIZE * SIZE) as usize);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let point = Vec2::new(x as f32, y as f32) / (SIZE - 1) as f32 - 0.5;
+            let wave = (point.length() * 30.0 - time * 4.0).sin();
+            let position = point * (1.0 + wave * 0.02);
+            let shade = 0.5 + wave * 0.5;
+            vertices.push(ColorVertex {
+                position: [position.x, position.y, 0.0],
+                color: [0.1, 0.3 + shade * 0.4, 0.6 + shade * 0.4, 1.0],
+            });
+        }
+    }
+    vertices
+}
@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use glam::{UVec2, Vec2};
+use image::RgbaImage;
+
+use crate::text::FontError;
+
+/// The most pages a font can have, which is as many as the binary format can refer to.
+const MAX_PAGES: usize = 256;
+
+/// A glyph cut out of a bitmap font's images.
+pub(crate) struct BitmapGlyph {
+    /// The offset from the glyph's origin to the bottom left of its image.
+    pub offset: Vec2,
+    pub size: UVec2,
+    pub advance: f32,
+    /// The alpha of every pixel of the glyph, from the top left.
+    pub coverage: Vec<u8>,
+}
+
+/// A font made of pre-drawn images, such as an AngelCode BMFont or a grid of characters.
+pub(crate) struct BitmapFont {
+    /// The size the glyphs were drawn at.
+    pub size: f32,
+    pub line_height: f32,
+    /// The distance from the top of a line to its baseline.
+    pub base: f32,
+    pub glyphs: Vec<BitmapGlyph>,
+    pub characters: HashMap<char, u16>,
+    pub kerning: HashMap<(u16, u16), f32>,
+}
+
+/// A character as it is described in a BMFont file.
+struct BmChar {
+    id: u32,
+    position: UVec2,
+    size: UVec2,
+    offset: Vec2,
+    advance: f32,
+    page: usize,
+}
+
+impl BitmapFont {
+    /// Load an AngelCode BMFont from its `.fnt` file, in either the text or binary
+    /// format. The pages are loaded from the same directory as the file.
+    pub fn load_bmfont(path: &Path) -> Result<Self, FontError> {
+        let contents = std::fs::read(path)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let load_page = |name: &str| -> Result<RgbaImage, FontError> {
+            Ok(image::open(directory.join(name))?.into_rgba8())
+        };
+
+        match contents.starts_with(b"BMF") {
+            true => Self::parse_binary(&contents, load_page),
+            false => Self::parse_text(&String::from_utf8_lossy(&contents), load_page),
+        }
+    }
+
+    fn parse_text(
+        contents: &str,
+        load_page: impl Fn(&str) -> Result<RgbaImage, FontError>,
+    ) -> Result<Self, FontError> {
+        let mut size = 0.0;
+        let mut line_height = 0.0;
+        let mut base = 0.0;
+        let mut page_count = MAX_PAGES;
+        let mut pages = Vec::new();
+        let mut chars = Vec::new();
+        let mut kerning = Vec::new();
+
+        for line in contents.lines() {
+            let (tag, values) = attributes(line);
+            let number = |key: &str| -> f32 {
+                values
+                    .get(key)
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(0.0)
+            };
+
+            match tag {
+                "info" => size = number("size").abs(),
+                "common" => {
+                    line_height = number("lineHeight");
+                    base = number("base");
+                    if values.contains_key("pages") {
+                        page_count = (number("pages") as usize).min(MAX_PAGES);
+                    }
+                }
+                "page" => {
+                    let id = number("id") as usize;
+                    if id >= page_count {
+                        return Err(FontError::Malformed("a page's id is past the page count"));
+                    }
+                    pages.resize(pages.len().max(id + 1), None);
+                    let file = values
+                        .get("file")
+                        .ok_or(FontError::Malformed("a page has no file"))?;
+                    pages[id] = Some(load_page(file)?);
+                }
+                "char" => chars.push(BmChar {
+                    id: number("id") as u32,
+                    position: Vec2::new(number("x"), number("y")).as_uvec2(),
+                    size: Vec2::new(number("width"), number("height")).as_uvec2(),
+                    offset: Vec2::new(number("xoffset"), number("yoffset")),
+                    advance: number("xadvance"),
+                    page: number("page") as usize,
+                }),
+                "kerning" => kerning.push((
+                    number("first") as u32,
+                    number("second") as u32,
+                    number("amount"),
+                )),
+                _ => {}
+            }
+        }
+
+        let pages = pages
+            .into_iter()
+            .map(|page| page.ok_or(FontError::Malformed("a page is missing")))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(size, line_height, base, &pages, chars, kerning)
+    }
+
+    fn parse_binary(
+        contents: &[u8],
+        load_page: impl Fn(&str) -> Result<RgbaImage, FontError>,
+    ) -> Result<Self, FontError> {
+        // Reads a little endian number, failing if the block is too short to hold it.
+        fn read<const N: usize>(data: &[u8], i: usize) -> Result<[u8; N], FontError> {
+            data.get(i..i + N)
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or(FontError::Malformed(
+                    "the file ends part way through a block",
+                ))
+        }
+        let u16_at = |data: &[u8], i| read(data, i).map(u16::from_le_bytes);
+        let i16_at = |data: &[u8], i| read(data, i).map(i16::from_le_bytes);
+        let u32_at = |data: &[u8], i| read(data, i).map(u32::from_le_bytes);
+
+        let mut size = 0.0;
+        let mut line_height = 0.0;
+        let mut base = 0.0;
+        let mut pages = Vec::new();
+        let mut chars = Vec::new();
+        let mut kerning = Vec::new();
+
+        // After the signature and version, the file is made of blocks that each start
+        // with their type and length.
+        let mut i = 4;
+        while i + 5 <= contents.len() {
+            let ty = contents[i];
+            let length = u32_at(contents, i + 1)? as usize;
+            let block = i
+                .checked_add(5 + length)
+                .and_then(|end| contents.get(i + 5..end))
+                .ok_or(FontError::Malformed(
+                    "the file ends part way through a block",
+                ))?;
+            i += 5 + length;
+
+            match ty {
+                1 => size = (i16_at(block, 0)? as f32).abs(),
+                2 => {
+                    line_height = u16_at(block, 0)? as f32;
+                    base = u16_at(block, 2)? as f32;
+                }
+                3 => {
+                    pages = block
+                        .split(|&byte| byte == 0)
+                        .filter(|name| !name.is_empty())
+                        .map(|name| load_page(&String::from_utf8_lossy(name)))
+                        .collect::<Result<_, _>>()?
+                }
+                4 => {
+                    for char in block.chunks_exact(20) {
+                        chars.push(BmChar {
+                            id: u32_at(char, 0)?,
+                            position: UVec2::new(u16_at(char, 4)? as u32, u16_at(char, 6)? as u32),
+                            size: UVec2::new(u16_at(char, 8)? as u32, u16_at(char, 10)? as u32),
+                            offset: Vec2::new(i16_at(char, 12)? as f32, i16_at(char, 14)? as f32),
+                            advance: i16_at(char, 16)? as f32,
+                            page: char[18] as usize,
+                        });
+                    }
+                }
+                5 => {
+                    for pair in block.chunks_exact(10) {
+                        kerning.push((u32_at(pair, 0)?, u32_at(pair, 4)?, i16_at(pair, 8)? as f32));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Self::new(size, line_height, base, &pages, chars, kerning)
+    }
+
+    fn new(
+        size: f32,
+        line_height: f32,
+        base: f32,
+        pages: &[RgbaImage],
+        chars: Vec<BmChar>,
+        kerning: Vec<(u32, u32, f32)>,
+    ) -> Result<Self, FontError> {
+        let mut font = Self {
+            size: if size > 0.0 { size } else { line_height },
+            line_height,
+            base,
+            glyphs: Vec::new(),
+            characters: HashMap::new(),
+            kerning: HashMap::new(),
+        };
+
+        for char in chars {
+            let character = match char::from_u32(char.id) {
+                Some(character) => character,
+                None => continue,
+            };
+            // BMFont measures offsets down from the top of the line.
+            let offset = Vec2::new(char.offset.x, base - char.offset.y - char.size.y as f32);
+            let page = pages.get(char.page).ok_or(FontError::Malformed(
+                "a character is on a page that doesn't exist",
+            ))?;
+            font.add_glyph(
+                character,
+                page,
+                char.position,
+                char.size,
+                offset,
+                char.advance,
+            );
+        }
+
+        for (first, second, amount) in kerning {
+            let index = |id| char::from_u32(id).and_then(|c| font.characters.get(&c).copied());
+            if let (Some(first), Some(second)) = (index(first), index(second)) {
+                font.kerning.insert((first, second), amount);
+            }
+        }
+
+        Ok(font)
+    }
+
+    /// Make a font from an image that is split into a grid of equally sized cells, each
+    /// holding one of `characters` in order from the top left, row by row.
+    pub fn from_grid(
+        image: &RgbaImage,
+        cell_size: UVec2,
+        characters: &str,
+    ) -> Result<Self, FontError> {
+        if cell_size.x == 0 || cell_size.y == 0 {
+            return Err(FontError::Malformed(
+                "the cells of a grid font can't be empty",
+            ));
+        }
+        let size = cell_size.y as f32;
+        let mut font = Self {
+            size,
+            line_height: size,
+            base: size,
+            glyphs: Vec::new(),
+            characters: HashMap::new(),
+            kerning: HashMap::new(),
+        };
+
+        let columns = (image.width() / cell_size.x).max(1);
+        for (i, character) in characters.chars().enumerate() {
+            let position = UVec2::new(i as u32 % columns, i as u32 / columns) * cell_size;
+            font.add_glyph(
+                character,
+                image,
+                position,
+                cell_size,
+                Vec2::ZERO,
+                cell_size.x as f32,
+            );
+        }
+
+        Ok(font)
+    }
+
+    fn add_glyph(
+        &mut self,
+        character: char,
+        page: &RgbaImage,
+        position: UVec2,
+        size: UVec2,
+        offset: Vec2,
+        advance: f32,
+    ) {
+        // Glyphs can't be bigger than their page, even in a malformed file.
+        let size = size.min(UVec2::new(page.width(), page.height()));
+        let mut coverage = Vec::with_capacity((size.x * size.y) as usize);
+        for y in position.y..position.y.saturating_add(size.y) {
+            for x in position.x..position.x.saturating_add(size.x) {
+                coverage.push(page.get_pixel_checked(x, y).map_or(0, |pixel| pixel[3]));
+            }
+        }
+
+        self.characters.insert(character, self.glyphs.len() as u16);
+        self.glyphs.push(BitmapGlyph {
+            offset,
+            size,
+            advance,
+            coverage,
+        });
+    }
+}
+
+/// Split a line of a text BMFont into its tag and its `key=value` attributes. Values can
+/// be quoted, in which case they can hold spaces.
+fn attributes(line: &str) -> (&str, HashMap<&str, &str>) {
+    let line = line.trim_start();
+    let (tag, mut rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+    let mut values = HashMap::new();
+    loop {
+        rest = rest.trim_start();
+        let (key, after) = match rest.split_once('=') {
+            Some(pair) => pair,
+            None => break,
+        };
+        // A word without an `=` before this one is skipped.
+        let key = key.rsplit(char::is_whitespace).next().unwrap_or(key);
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_at(after.find(char::is_whitespace).unwrap_or(after.len())),
+        };
+        values.insert(key, value);
+        rest = after;
+    }
+    (tag, values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A page whose alpha is the sum of each pixel's coordinates.
+    fn page(name: &str) -> Result<RgbaImage, FontError> {
+        assert_eq!(name, "font page.png");
+        Ok(RgbaImage::from_fn(16, 16, |x, y| {
+            image::Rgba([255, 255, 255, (x + y) as u8])
+        }))
+    }
+
+    const TEXT: &str = r#"info face="Pixel Sans" size=-16 bold=0
+common lineHeight=20 base=16 pages=1
+page id=0 file="font page.png"
+chars count=2
+char id=65 x=0 y=0 width=4 height=8 xoffset=1 yoffset=2 xadvance=5 page=0
+char id=66   x=4 y=8 width=4 height=8 xoffset=0 yoffset=4 xadvance=6 page=0
+kernings count=1
+kerning first=65 second=66 amount=-1
+"#;
+
+    #[test]
+    fn text_chars_and_kerning() {
+        let font = BitmapFont::parse_text(TEXT, page).unwrap();
+        assert_eq!(font.size, 16.0);
+        assert_eq!(font.line_height, 20.0);
+        assert_eq!(font.base, 16.0);
+
+        let (a, b) = (font.characters[&'A'], font.characters[&'B']);
+        let glyph = &font.glyphs[a as usize];
+        assert_eq!(glyph.size, UVec2::new(4, 8));
+        assert_eq!(glyph.offset, Vec2::new(1.0, 6.0));
+        assert_eq!(glyph.advance, 5.0);
+        assert_eq!(glyph.coverage[..4], [0, 1, 2, 3]);
+        assert_eq!(font.glyphs[b as usize].coverage[0], 12);
+        assert_eq!(font.kerning[&(a, b)], -1.0);
+    }
+
+    #[test]
+    fn text_quoted_values() {
+        let (tag, values) = attributes(r#"info face="Pixel Sans" charset="" size=16"#);
+        assert_eq!(tag, "info");
+        assert_eq!(values["face"], "Pixel Sans");
+        assert_eq!(values["charset"], "");
+        assert_eq!(values["size"], "16");
+    }
+
+    #[test]
+    fn text_malformed() {
+        let malformed = |contents: &str| {
+            matches!(
+                BitmapFont::parse_text(contents, page),
+                Err(FontError::Malformed(_))
+            )
+        };
+        assert!(malformed("page id=4000000000 file=\"font page.png\""));
+        assert!(malformed(
+            "common pages=1\npage id=1 file=\"font page.png\""
+        ));
+        assert!(malformed("page id=0"));
+        assert!(malformed(
+            "page id=1 file=\"font page.png\"\nchar id=65 width=1 height=1"
+        ));
+        assert!(malformed("char id=65 width=1 height=1 page=0"));
+    }
+
+    /// A binary font with the same characters and kerning as `TEXT`.
+    fn binary() -> Vec<u8> {
+        let mut bytes = b"BMF\x03".to_vec();
+        let mut block = |ty: u8, data: &[u8]| {
+            bytes.push(ty);
+            bytes.extend((data.len() as u32).to_le_bytes());
+            bytes.extend(data);
+        };
+        block(1, &(-16i16).to_le_bytes());
+        block(2, &[20, 0, 16, 0]);
+        block(3, b"font page.png\0");
+        let char = |id: u32, position: [u16; 2], offset: [i16; 2], advance: i16| {
+            let mut data = id.to_le_bytes().to_vec();
+            for value in [position[0], position[1], 4, 8] {
+                data.extend(value.to_le_bytes());
+            }
+            for value in [offset[0], offset[1], advance] {
+                data.extend(value.to_le_bytes());
+            }
+            data.extend([0, 15]);
+            data
+        };
+        block(
+            4,
+            &[char(65, [0, 0], [1, 2], 5), char(66, [4, 8], [0, 4], 6)].concat(),
+        );
+        let mut pair = 65u32.to_le_bytes().to_vec();
+        pair.extend(66u32.to_le_bytes());
+        pair.extend((-1i16).to_le_bytes());
+        block(5, &pair);
+        bytes
+    }
+
+    #[test]
+    fn binary_chars_and_kerning() {
+        let font = BitmapFont::parse_binary(&binary(), page).unwrap();
+        assert_eq!(font.size, 16.0);
+        assert_eq!(font.line_height, 20.0);
+
+        let (a, b) = (font.characters[&'A'], font.characters[&'B']);
+        assert_eq!(font.glyphs[a as usize].offset, Vec2::new(1.0, 6.0));
+        assert_eq!(font.glyphs[b as usize].advance, 6.0);
+        assert_eq!(font.glyphs[b as usize].coverage[0], 12);
+        assert_eq!(font.kerning[&(a, b)], -1.0);
+    }
+
+    #[test]
+    fn binary_malformed() {
+        let mut bytes = binary();
+        bytes.truncate(bytes.len() - 3);
+        assert!(matches!(
+            BitmapFont::parse_binary(&bytes, page),
+            Err(FontError::Malformed(_))
+        ));
+
+        // A block that claims to be longer than the whole file.
+        let mut bytes = b"BMF\x03\x01".to_vec();
+        bytes.extend(u32::MAX.to_le_bytes());
+        assert!(matches!(
+            BitmapFont::parse_binary(&bytes, page),
+            Err(FontError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,25 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+// Covers the viewport with a single triangle, without needing a vertex buffer.
+@vertex
+fn vertex_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let tex_coords = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var output: VertexOutput;
+    output.clip_position = vec4<f32>(tex_coords * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    output.tex_coords = tex_coords;
+    return output;
+}
+
+@group(0)@binding(0)
+var texture: texture_2d<f32>;
+@group(0)@binding(1)
+var texture_sampler: sampler;
+
+@fragment
+fn fragment_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(texture, texture_sampler, vertex.tex_coords);
+}
@@ -0,0 +1,105 @@
+use glam::{Mat4, Vec2};
+
+use crate::rect::Rect;
+use crate::render::PixelRect;
+
+/// A view into the world, used by layers to decide what part of the world is visible.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    /// The point in the world at the centre of the view.
+    pub position: Vec2,
+    /// How much the view is magnified, a zoom of 2 makes everything twice as big.
+    pub zoom: f32,
+    /// The camera's rotation in radians, counterclockwise.
+    pub rotation: f32,
+}
+
+impl Camera {
+    pub fn new(position: Vec2) -> Self {
+        Self {
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_position(mut self, position: Vec2) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn with_zoom(mut self, zoom: f32) -> Self {
+        self.zoom = zoom;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// The matrix that moves a point in the world onto the screen, where `frame_size`
+    /// is the amount of the world visible at a zoom of 1.
+    pub(crate) fn matrix(&self, frame_size: Vec2) -> Mat4 {
+        let half = frame_size / 2.0;
+        let projection = Mat4::orthographic_rh(-half.x, half.x, -half.y, half.y, -100.0, 100.0);
+
+        projection
+            * Mat4::from_scale(Vec2::splat(self.zoom).extend(1.0))
+            * Mat4::from_rotation_z(-self.rotation)
+            * Mat4::from_translation(-self.position.extend(0.0))
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            zoom: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+/// An area of the window that the world is drawn into through its own camera, used for
+/// split screen.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    /// The area of the window that is covered, where 0,0 is the bottom left corner of the
+    /// window and 1,1 is the top right.
+    pub rect: Rect,
+    pub camera: Camera,
+}
+
+impl Viewport {
+    pub fn new(rect: Rect, camera: Camera) -> Self {
+        Self { rect, camera }
+    }
+
+    /// Split the window evenly between the cameras. Two cameras are placed side by side,
+    /// and three or four are placed in a grid starting from the top left.
+    pub fn split(cameras: &[Camera]) -> Vec<Self> {
+        let columns = if cameras.len() > 1 { 2 } else { 1 };
+        let rows = cameras.len().div_ceil(columns);
+        let size = Vec2::new(1.0 / columns as f32, 1.0 / rows.max(1) as f32);
+
+        cameras
+            .iter()
+            .enumerate()
+            .map(|(i, camera)| {
+                let min = Vec2::new((i % columns) as f32, (rows - 1 - i / columns) as f32) * size;
+                Self::new(Rect::new(min, min + size), *camera)
+            })
+            .collect()
+    }
+
+    pub(crate) fn pixel_rect(&self, window_size: Vec2) -> PixelRect {
+        let min = (Vec2::new(self.rect.min.x, 1.0 - self.rect.max.y) * window_size)
+            .round()
+            .clamp(Vec2::ZERO, window_size);
+        let max = (Vec2::new(self.rect.max.x, 1.0 - self.rect.min.y) * window_size)
+            .round()
+            .clamp(min, window_size);
+        let size = max - min;
+        (min.x as u32, min.y as u32, size.x as u32, size.y as u32)
+    }
+}
@@ -3,8 +3,13 @@ struct Transform {
     @location(3) data1: vec4<f32>,
     @location(4) data2: vec4<f32>,
     @location(5) data3: vec4<f32>,
+    // Multiplies the color of the sprite.
+    @location(9) tint: vec4<f32>,
 }
 
+@group(0)@binding(0)
+var<uniform> camera: mat4x4<f32>;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec4<f32>,
@@ -25,8 +30,8 @@ fn vertex_main(vertex: VertexInput, transform: Transform) -> VertexOutput {
     );
 
     var output: VertexOutput;
-    output.clip_position = transform_matrix * vec4<f32>(vertex.position, 1.0);
-    output.color = vertex.color;
+    output.clip_position = camera * transform_matrix * vec4<f32>(vertex.position, 1.0);
+    output.color = vertex.color * transform.tint;
     return output;
 }
 
@@ -34,3 +39,12 @@ fn vertex_main(vertex: VertexInput, transform: Transform) -> VertexOutput {
 fn fragment_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
     return vertex.color;
 }
+
+// Used when drawing the sprite as a mask, where only its shape matters.
+@fragment
+fn fragment_mask(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    if (vertex.color.a < 0.5) {
+        discard;
+    }
+    return vertex.color;
+}
@@ -0,0 +1,644 @@
+use image::{Rgba, RgbaImage};
+use wgpu::TextureFormat;
+
+use crate::graphics::TextureError;
+
+/// The first bytes of every KTX2 file.
+const KTX2_MAGIC: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// A texture stored as blocks of 4x4 pixels that the GPU can sample without
+/// decompressing, loaded from a KTX2 or DDS file.
+pub(crate) struct CompressedImage {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// The blocks of every mipmap level, starting with the full size image.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    /// If `bytes` start like a KTX2 or DDS file.
+    pub fn is_container(bytes: &[u8]) -> bool {
+        bytes.starts_with(&KTX2_MAGIC) || bytes.starts_with(b"DDS ")
+    }
+
+    /// Load the first image of a KTX2 or DDS file, which must hold BC1 to BC7 data.
+    pub fn load(bytes: &[u8]) -> Result<Self, TextureError> {
+        let image = match bytes.starts_with(b"DDS ") {
+            true => Self::load_dds(bytes)?,
+            false => Self::load_ktx2(bytes)?,
+        };
+
+        if image.width == 0 || image.height == 0 {
+            return Err(malformed("the image has no pixels"));
+        }
+
+        // Files with several layers or faces store them one after the other in each
+        // level, so only the first is kept. Levels that are too short, or smaller than
+        // a single pixel, are dropped.
+        let block_size = image.format.describe().block_size as usize;
+        let levels: Vec<_> = image
+            .levels
+            .into_iter()
+            .take(mip_count(image.width, image.height) as usize)
+            .enumerate()
+            .map_while(|(level, mut data)| {
+                let (columns, rows) = blocks(image.width >> level, image.height >> level);
+                let size = columns as usize * rows as usize * block_size;
+                data.truncate(size);
+                (data.len() == size).then_some(data)
+            })
+            .collect();
+        if levels.is_empty() {
+            return Err(malformed("the image data is missing or too short"));
+        }
+        Ok(Self { levels, ..image })
+    }
+
+    fn load_ktx2(bytes: &[u8]) -> Result<Self, TextureError> {
+        let reader = ktx2::Reader::new(bytes).map_err(|error| malformed(error.to_string()))?;
+        let header = reader.header();
+        if header.supercompression_scheme.is_some() {
+            return Err(malformed("supercompressed KTX2 files aren't supported"));
+        }
+
+        use ktx2::Format as K;
+        let format = match header.format {
+            Some(K::BC1_RGB_UNORM_BLOCK | K::BC1_RGBA_UNORM_BLOCK) => TextureFormat::Bc1RgbaUnorm,
+            Some(K::BC1_RGB_SRGB_BLOCK | K::BC1_RGBA_SRGB_BLOCK) => TextureFormat::Bc1RgbaUnormSrgb,
+            Some(K::BC2_UNORM_BLOCK) => TextureFormat::Bc2RgbaUnorm,
+            Some(K::BC2_SRGB_BLOCK) => TextureFormat::Bc2RgbaUnormSrgb,
+            Some(K::BC3_UNORM_BLOCK) => TextureFormat::Bc3RgbaUnorm,
+            Some(K::BC3_SRGB_BLOCK) => TextureFormat::Bc3RgbaUnormSrgb,
+            Some(K::BC4_UNORM_BLOCK) => TextureFormat::Bc4RUnorm,
+            Some(K::BC4_SNORM_BLOCK) => TextureFormat::Bc4RSnorm,
+            Some(K::BC5_UNORM_BLOCK) => TextureFormat::Bc5RgUnorm,
+            Some(K::BC5_SNORM_BLOCK) => TextureFormat::Bc5RgSnorm,
+            Some(K::BC6H_UFLOAT_BLOCK) => TextureFormat::Bc6hRgbUfloat,
+            Some(K::BC6H_SFLOAT_BLOCK) => TextureFormat::Bc6hRgbSfloat,
+            Some(K::BC7_UNORM_BLOCK) => TextureFormat::Bc7RgbaUnorm,
+            Some(K::BC7_SRGB_BLOCK) => TextureFormat::Bc7RgbaUnormSrgb,
+            _ => return Err(malformed("the KTX2 file doesn't hold BC compressed data")),
+        };
+
+        Ok(Self {
+            format,
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            levels: reader.levels().map(<[u8]>::to_vec).collect(),
+        })
+    }
+
+    fn load_dds(bytes: &[u8]) -> Result<Self, TextureError> {
+        let dds = ddsfile::Dds::read(bytes).map_err(|error| malformed(error.to_string()))?;
+
+        // Older files without a DXGI format are treated as sRGB colors.
+        use ddsfile::DxgiFormat as D;
+        let format = match dds.get_dxgi_format() {
+            Some(D::BC1_Typeless | D::BC1_UNorm) => TextureFormat::Bc1RgbaUnorm,
+            Some(D::BC1_UNorm_sRGB) => TextureFormat::Bc1RgbaUnormSrgb,
+            Some(D::BC2_Typeless | D::BC2_UNorm) => TextureFormat::Bc2RgbaUnorm,
+            Some(D::BC2_UNorm_sRGB) => TextureFormat::Bc2RgbaUnormSrgb,
+            Some(D::BC3_Typeless | D::BC3_UNorm) => TextureFormat::Bc3RgbaUnorm,
+            Some(D::BC3_UNorm_sRGB) => TextureFormat::Bc3RgbaUnormSrgb,
+            Some(D::BC4_Typeless | D::BC4_UNorm) => TextureFormat::Bc4RUnorm,
+            Some(D::BC4_SNorm) => TextureFormat::Bc4RSnorm,
+            Some(D::BC5_Typeless | D::BC5_UNorm) => TextureFormat::Bc5RgUnorm,
+            Some(D::BC5_SNorm) => TextureFormat::Bc5RgSnorm,
+            Some(D::BC6H_Typeless | D::BC6H_UF16) => TextureFormat::Bc6hRgbUfloat,
+            Some(D::BC6H_SF16) => TextureFormat::Bc6hRgbSfloat,
+            Some(D::BC7_Typeless | D::BC7_UNorm) => TextureFormat::Bc7RgbaUnorm,
+            Some(D::BC7_UNorm_sRGB) => TextureFormat::Bc7RgbaUnormSrgb,
+            _ => return Err(malformed("the DDS file doesn't hold BC compressed data")),
+        };
+
+        // Every level is stored one after the other.
+        let (width, height) = (dds.get_width(), dds.get_height().max(1));
+        let block_size = format.describe().block_size as usize;
+        let mut data = dds
+            .get_data(0)
+            .map_err(|error| malformed(error.to_string()))?;
+        let level_count = dds
+            .get_num_mipmap_levels()
+            .clamp(1, mip_count(width, height));
+        let levels = (0..level_count)
+            .map_while(|level| {
+                let (columns, rows) = blocks(width >> level, height >> level);
+                let size = columns as usize * rows as usize * block_size;
+                let level = data.get(..size)?.to_vec();
+                data = &data[size..];
+                Some(level)
+            })
+            .collect();
+
+        Ok(Self {
+            format,
+            width,
+            height,
+            levels,
+        })
+    }
+
+    /// If the size of the image is a whole number of blocks, which the GPU needs to
+    /// sample it compressed.
+    pub fn is_block_aligned(&self) -> bool {
+        self.width.is_multiple_of(4) && self.height.is_multiple_of(4)
+    }
+
+    /// If every pixel of the image is known to be opaque from its format alone.
+    pub fn is_opaque(&self) -> bool {
+        matches!(
+            self.format,
+            TextureFormat::Bc4RUnorm
+                | TextureFormat::Bc4RSnorm
+                | TextureFormat::Bc5RgUnorm
+                | TextureFormat::Bc5RgSnorm
+                | TextureFormat::Bc6hRgbUfloat
+                | TextureFormat::Bc6hRgbSfloat
+        )
+    }
+
+    /// Decompress the full size image on the CPU, for devices that can't sample it
+    /// compressed. BC6H holds HDR colors, so it can't be decoded to 8 bit RGBA.
+    pub fn decode(&self) -> Result<RgbaImage, TextureError> {
+        if let TextureFormat::Bc6hRgbUfloat | TextureFormat::Bc6hRgbSfloat = self.format {
+            return Err(malformed("BC6H textures can't be decoded on the CPU"));
+        }
+
+        let block_size = self.format.describe().block_size as usize;
+        let (columns, _) = blocks(self.width, self.height);
+        let mut image = RgbaImage::new(self.width, self.height);
+
+        for (i, block) in self.levels[0].chunks_exact(block_size).enumerate() {
+            let (left, top) = (i as u32 % columns * 4, i as u32 / columns * 4);
+            for (j, pixel) in decode_block(self.format, block).into_iter().enumerate() {
+                let (x, y) = (left + j as u32 % 4, top + j as u32 / 4);
+                if x < self.width && y < self.height {
+                    image.put_pixel(x, y, Rgba(pixel));
+                }
+            }
+        }
+        Ok(image)
+    }
+}
+
+fn malformed(reason: impl Into<String>) -> TextureError {
+    TextureError::Compressed(reason.into())
+}
+
+/// The number of levels in a full chain of mipmaps, down to a single pixel.
+fn mip_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// The number of blocks across and down an image of `width` by `height` pixels.
+pub(crate) fn blocks(width: u32, height: u32) -> (u32, u32) {
+    (width.max(1).div_ceil(4), height.max(1).div_ceil(4))
+}
+
+/// Decode the 16 pixels of a block, row by row from the top left.
+fn decode_block(format: TextureFormat, block: &[u8]) -> [[u8; 4]; 16] {
+    match format {
+        TextureFormat::Bc1RgbaUnorm | TextureFormat::Bc1RgbaUnormSrgb => bc1(block, true),
+        TextureFormat::Bc2RgbaUnorm | TextureFormat::Bc2RgbaUnormSrgb => {
+            let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+            let mut pixels = bc1(&block[8..], false);
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                pixel[3] = (alpha >> (i * 4) & 0xF) as u8 * 17;
+            }
+            pixels
+        }
+        TextureFormat::Bc3RgbaUnorm | TextureFormat::Bc3RgbaUnormSrgb => {
+            let alpha = bc4(&block[..8], false);
+            let mut pixels = bc1(&block[8..], false);
+            for (pixel, alpha) in pixels.iter_mut().zip(alpha) {
+                pixel[3] = alpha;
+            }
+            pixels
+        }
+        TextureFormat::Bc4RUnorm | TextureFormat::Bc4RSnorm => {
+            let red = bc4(block, format == TextureFormat::Bc4RSnorm);
+            red.map(|red| [red, 0, 0, u8::MAX])
+        }
+        TextureFormat::Bc5RgUnorm | TextureFormat::Bc5RgSnorm => {
+            let signed = format == TextureFormat::Bc5RgSnorm;
+            let (red, green) = (bc4(&block[..8], signed), bc4(&block[8..], signed));
+            let mut pixels = [[0, 0, 0, u8::MAX]; 16];
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                pixel[0] = red[i];
+                pixel[1] = green[i];
+            }
+            pixels
+        }
+        TextureFormat::Bc7RgbaUnorm | TextureFormat::Bc7RgbaUnormSrgb => bc7(block),
+        _ => unreachable!("{:?} textures are rejected before decoding", format),
+    }
+}
+
+/// Decode a block of two RGB565 colors and a 2 bit index per pixel. Only BC1 on its
+/// own can use the fourth color as transparent black.
+fn bc1(block: &[u8], punch_through: bool) -> [[u8; 4]; 16] {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+
+    let expand = |color: u16| {
+        let (r, g, b) = (color >> 11 & 0x1F, color >> 5 & 0x3F, color & 0x1F);
+        [(r << 3 | r >> 2), (g << 2 | g >> 4), (b << 3 | b >> 2)].map(|c| c as u32)
+    };
+    let (a, b) = (expand(color0), expand(color1));
+    let mix = |wa: u32, wb: u32| {
+        let total = wa + wb;
+        [0, 1, 2].map(|c| ((a[c] * wa + b[c] * wb) / total) as u8)
+    };
+
+    let opaque = |[r, g, b]: [u8; 3]| [r, g, b, u8::MAX];
+    let palette = match color0 > color1 || !punch_through {
+        true => [
+            opaque(mix(1, 0)),
+            opaque(mix(0, 1)),
+            opaque(mix(2, 1)),
+            opaque(mix(1, 2)),
+        ],
+        false => [
+            opaque(mix(1, 0)),
+            opaque(mix(0, 1)),
+            opaque(mix(1, 1)),
+            [0; 4],
+        ],
+    };
+    std::array::from_fn(|i| palette[(indices >> (i * 2) & 3) as usize])
+}
+
+/// Decode a block of two 8 bit values and a 3 bit index per pixel. Signed values are
+/// clamped to zero, the same as they are when drawn.
+fn bc4(block: &[u8], signed: bool) -> [u8; 16] {
+    let indices = u64::from_le_bytes(block[..8].try_into().unwrap()) >> 16;
+    let (a, b) = match signed {
+        true => (
+            (block[0] as i8).max(-127) as i32,
+            (block[1] as i8).max(-127) as i32,
+        ),
+        false => (block[0] as i32, block[1] as i32),
+    };
+
+    let mut palette = [a, b, 0, 0, 0, 0, 0, 0];
+    if a > b {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as i32) * a + i as i32 * b) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as i32) * a + i as i32 * b) / 5;
+        }
+        (palette[6], palette[7]) = match signed {
+            true => (-127, 127),
+            false => (0, 255),
+        };
+    }
+
+    std::array::from_fn(|i| {
+        let value = palette[(indices >> (i * 3) & 7) as usize];
+        match signed {
+            true => (value.max(0) * 255 / 127) as u8,
+            false => value as u8,
+        }
+    })
+}
+
+/// The layout of one of the eight BC7 modes.
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    /// If every endpoint has its own extra low bit.
+    endpoint_pbits: bool,
+    /// If both endpoints of a subset share an extra low bit.
+    shared_pbits: bool,
+    index_bits: u32,
+    /// The size of the second set of indices, used by the modes that index alpha
+    /// separately.
+    index_bits2: u32,
+}
+
+impl Bc7Mode {
+    /// Make a mode from its subsets, partition, rotation, selection, color and alpha
+    /// bits, P-bits (none, per endpoint or shared), and the sizes of its indices.
+    const fn new(fields: [u32; 9]) -> Self {
+        Self {
+            subsets: fields[0] as usize,
+            partition_bits: fields[1],
+            rotation_bits: fields[2],
+            selection_bits: fields[3],
+            color_bits: fields[4],
+            alpha_bits: fields[5],
+            endpoint_pbits: fields[6] == 1,
+            shared_pbits: fields[6] == 2,
+            index_bits: fields[7],
+            index_bits2: fields[8],
+        }
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode::new([3, 4, 0, 0, 4, 0, 1, 3, 0]),
+    Bc7Mode::new([2, 6, 0, 0, 6, 0, 2, 3, 0]),
+    Bc7Mode::new([3, 6, 0, 0, 5, 0, 0, 2, 0]),
+    Bc7Mode::new([2, 6, 0, 0, 7, 0, 1, 2, 0]),
+    Bc7Mode::new([1, 0, 2, 1, 5, 6, 0, 2, 3]),
+    Bc7Mode::new([1, 0, 2, 0, 7, 8, 0, 2, 2]),
+    Bc7Mode::new([1, 0, 0, 0, 7, 7, 1, 4, 0]),
+    Bc7Mode::new([2, 6, 0, 0, 5, 5, 1, 2, 0]),
+];
+
+/// The subset of every pixel in the two subset partitions, one bit per pixel.
+const PARTITIONS2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800,
+    0xFFE8, 0xFF00, 0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC,
+    0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718,
+    0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// The subset of every pixel in the three subset partitions, two bits per pixel.
+const PARTITIONS3: [u32; 64] = [
+    0xAA685050, 0x6A5A5040, 0x5A5A4200, 0x5450A0A8, 0xA5A50000, 0xA0A05050, 0x5555A0A0, 0x5A5A5050,
+    0xAA550000, 0xAA555500, 0xAAAA5500, 0x90909090, 0x94949494, 0xA4A4A4A4, 0xA9A59450, 0x2A0A4250,
+    0xA5945040, 0x0A425054, 0xA5A5A500, 0x55A0A0A0, 0xA8A85454, 0x6A6A4040, 0xA4A45000, 0x1A1A0500,
+    0x0050A4A4, 0xAAA59090, 0x14696914, 0x69691400, 0xA08585A0, 0xAA821414, 0x50A4A450, 0x6A5A0200,
+    0xA9A58000, 0x5090A0A8, 0xA8A09050, 0x24242424, 0x00AA5500, 0x24924924, 0x24499224, 0x50A50A50,
+    0x500AA550, 0xAAAA4444, 0x66660000, 0xA5A0A5A0, 0x50A050A0, 0x69286928, 0x44AAAA44, 0x66666600,
+    0xAA444444, 0x54A854A8, 0x95809580, 0x96969600, 0xA85454A8, 0x80959580, 0xAA141414, 0x96960000,
+    0xAAAA1414, 0xA05050A0, 0xA0A5A5A0, 0x96000000, 0x40804080, 0xA9A8A9A8, 0xAAAAAA44, 0x2A4A5254,
+];
+
+/// The pixel of the second subset whose index has one bit fewer, in the two subset
+/// partitions. The first subset's is always pixel 0.
+const ANCHORS2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// The anchor pixels of the second and third subsets in the three subset partitions.
+const ANCHORS3: [[usize; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6,
+        8, 5, 15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8,
+        5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3,
+        15, 6, 10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15,
+        15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+    ],
+];
+
+/// Reads a block's fields from its lowest bit upwards.
+struct Bits {
+    data: u128,
+    position: u32,
+}
+
+impl Bits {
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.data >> self.position) as u32 & ((1 << count) - 1);
+        self.position += count;
+        value
+    }
+}
+
+/// Blend between two endpoints with an index of `bits` bits.
+fn interpolate(a: u32, b: u32, index: u32, bits: u32) -> u8 {
+    const WEIGHTS2: [u32; 4] = [0, 21, 43, 64];
+    const WEIGHTS3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+    const WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+    let weight = match bits {
+        2 => WEIGHTS2[index as usize],
+        3 => WEIGHTS3[index as usize],
+        _ => WEIGHTS4[index as usize],
+    };
+    (((64 - weight) * a + weight * b + 32) >> 6) as u8
+}
+
+fn bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut bits = Bits {
+        data: u128::from_le_bytes(block.try_into().unwrap()),
+        position: 0,
+    };
+    // The mode is the position of the lowest set bit, blocks without one are invalid.
+    let mode = block[0].trailing_zeros();
+    if mode >= 8 {
+        return [[0; 4]; 16];
+    }
+    bits.read(mode + 1);
+    let info = &BC7_MODES[mode as usize];
+    let partition = bits.read(info.partition_bits) as usize;
+    let rotation = bits.read(info.rotation_bits);
+    let selection = bits.read(info.selection_bits);
+
+    // Each channel is stored for every endpoint before the next channel.
+    let endpoint_count = info.subsets * 2;
+    let mut endpoints = [[0; 4]; 6];
+    let channel_bits = [
+        info.color_bits,
+        info.color_bits,
+        info.color_bits,
+        info.alpha_bits,
+    ];
+    for (channel, &count) in channel_bits.iter().enumerate() {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = bits.read(count);
+        }
+    }
+
+    let mut pbits = [None; 6];
+    if info.endpoint_pbits {
+        for pbit in &mut pbits[..endpoint_count] {
+            *pbit = Some(bits.read(1));
+        }
+    } else if info.shared_pbits {
+        for subset in 0..info.subsets {
+            let pbit = bits.read(1);
+            pbits[subset * 2] = Some(pbit);
+            pbits[subset * 2 + 1] = Some(pbit);
+        }
+    }
+
+    // Expand every endpoint to 8 bits by repeating its highest bits below it.
+    for (endpoint, pbit) in endpoints[..endpoint_count].iter_mut().zip(pbits) {
+        for (value, &count) in endpoint.iter_mut().zip(&channel_bits) {
+            if count == 0 {
+                *value = 255;
+                continue;
+            }
+            let (mut expanded, mut count) = (*value, count);
+            if let Some(pbit) = pbit {
+                expanded = expanded << 1 | pbit;
+                count += 1;
+            }
+            expanded <<= 8 - count;
+            *value = expanded | expanded >> count;
+        }
+    }
+
+    let subset = |pixel: usize| match info.subsets {
+        1 => 0,
+        2 => (PARTITIONS2[partition] >> pixel & 1) as usize,
+        _ => (PARTITIONS3[partition] >> (pixel * 2) & 3) as usize,
+    };
+    // The highest bit of the first index of each subset is always zero, so it isn't
+    // stored.
+    let is_anchor = |pixel: usize| match info.subsets {
+        _ if pixel == 0 => true,
+        1 => false,
+        2 => pixel == ANCHORS2[partition],
+        _ => pixel == ANCHORS3[0][partition] || pixel == ANCHORS3[1][partition],
+    };
+    let indices: [u32; 16] =
+        std::array::from_fn(|pixel| bits.read(info.index_bits - is_anchor(pixel) as u32));
+    let indices2: [u32; 16] = std::array::from_fn(|pixel| match info.index_bits2 {
+        0 => 0,
+        count => bits.read(count - (pixel == 0) as u32),
+    });
+
+    std::array::from_fn(|pixel| {
+        let subset = subset(pixel);
+        let (a, b) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+        let (color, alpha) = match (info.index_bits2, selection) {
+            (0, _) => (
+                (indices[pixel], info.index_bits),
+                (indices[pixel], info.index_bits),
+            ),
+            (_, 0) => (
+                (indices[pixel], info.index_bits),
+                (indices2[pixel], info.index_bits2),
+            ),
+            _ => (
+                (indices2[pixel], info.index_bits2),
+                (indices[pixel], info.index_bits),
+            ),
+        };
+
+        let mut rgba = [0; 4];
+        for channel in 0..3 {
+            rgba[channel] = interpolate(a[channel], b[channel], color.0, color.1);
+        }
+        rgba[3] = interpolate(a[3], b[3], alpha.0, alpha.1);
+        // Some modes swap alpha with a color channel to give that channel more precision.
+        if rotation > 0 {
+            rgba.swap(rotation as usize - 1, 3);
+        }
+        rgba
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pack fields of the given sizes into a block, from its lowest bit upwards.
+    fn pack(fields: &[(u128, u32)]) -> [u8; 16] {
+        let mut data = 0;
+        let mut position = 0;
+        for &(value, bits) in fields {
+            data |= value << position;
+            position += bits;
+        }
+        assert!(position <= 128);
+        data.to_le_bytes()
+    }
+
+    /// Pack a 2 bit index for every pixel into the last 32 bits of a BC1 block.
+    fn bc1_block(color0: u16, color1: u16, indices: [u32; 16]) -> [u8; 8] {
+        let mut block = [0; 8];
+        block[..2].copy_from_slice(&color0.to_le_bytes());
+        block[2..4].copy_from_slice(&color1.to_le_bytes());
+        let indices = (0..16).fold(0u32, |packed, i| packed | indices[i] << (i * 2));
+        block[4..].copy_from_slice(&indices.to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn bc1_decodes_palette() {
+        let mut indices = [0; 16];
+        indices[1] = 1;
+        indices[2] = 2;
+        indices[3] = 3;
+        let pixels = bc1(&bc1_block(0xF800, 0x001F, indices), true);
+        assert_eq!(pixels[0], [255, 0, 0, 255]);
+        assert_eq!(pixels[1], [0, 0, 255, 255]);
+        assert_eq!(pixels[2], [170, 0, 85, 255]);
+        assert_eq!(pixels[3], [85, 0, 170, 255]);
+    }
+
+    #[test]
+    fn bc1_punch_through() {
+        let mut indices = [0; 16];
+        indices[1] = 2;
+        indices[2] = 3;
+        let block = bc1_block(0x001F, 0xF800, indices);
+
+        let pixels = bc1(&block, true);
+        assert_eq!(pixels[0], [0, 0, 255, 255]);
+        assert_eq!(pixels[1], [127, 0, 127, 255]);
+        assert_eq!(pixels[2], [0; 4]);
+
+        // Inside BC2 and BC3 blocks the fourth color is always opaque.
+        assert_eq!(bc1(&block, false)[2], [170, 0, 85, 255]);
+    }
+
+    #[test]
+    fn bc4_decodes_unsigned() {
+        let block = pack(&[(255, 8), (0, 8), (0, 3), (1, 3), (2, 3), (7, 3)]);
+        let values = bc4(&block[..8], false);
+        assert_eq!(values[..4], [255, 0, 218, 36]);
+    }
+
+    #[test]
+    fn bc4_decodes_signed() {
+        let block = pack(&[(0x7F, 8), (0x81, 8), (0, 3), (1, 3), (2, 3)]);
+        let values = bc4(&block[..8], true);
+        assert_eq!(values[..3], [255, 0, 180]);
+    }
+
+    #[test]
+    fn bc7_mode6() {
+        // Endpoint A is 127 with a P-bit of 1 in every channel, endpoint B is zero.
+        let mut fields = vec![(1 << 6, 7)];
+        for _ in 0..4 {
+            fields.extend([(127, 7), (0, 7)]);
+        }
+        fields.extend([(1, 1), (0, 1), (0, 3), (15, 4), (8, 4)]);
+
+        let pixels = bc7(&pack(&fields));
+        assert_eq!(pixels[0], [255; 4]);
+        assert_eq!(pixels[1], [0; 4]);
+        assert_eq!(pixels[2], [120; 4]);
+        assert_eq!(pixels[15], [255; 4]);
+    }
+
+    #[test]
+    fn bc7_mode5_rotation() {
+        // Only endpoint A's red is set, and rotation 1 swaps red with alpha.
+        let mut fields = vec![(1 << 5, 6), (1, 2), (127, 7)];
+        let pixels = bc7(&pack(&fields));
+        assert_eq!(pixels[0], [0, 0, 0, 255]);
+
+        fields[1] = (0, 2);
+        let pixels = bc7(&pack(&fields));
+        assert_eq!(pixels[0], [255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bc7_invalid_block() {
+        assert_eq!(bc7(&[0; 16]), [[0; 4]; 16]);
+    }
+}
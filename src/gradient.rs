@@ -0,0 +1,172 @@
+use glam::{const_vec2, Vec2, Vec4};
+use wgpu::Color;
+
+use crate::sprite::ColorTextureVertex;
+
+/// The number of rings between the centre and the edge of a filled shape. Colours are
+/// blended linearly between vertices, so radial gradients and extra stops need them.
+const RINGS: u32 = 16;
+/// The number of pieces each edge of a filled shape is split into.
+const EDGE_STEPS: u32 = 8;
+
+/// The corners of a rect covering a sprite, in the sprite's local space.
+pub const RECT: [Vec2; 4] = [
+    const_vec2!([-0.5, -0.5]),
+    const_vec2!([0.5, -0.5]),
+    const_vec2!([0.5, 0.5]),
+    const_vec2!([-0.5, 0.5]),
+];
+
+/// Where a gradient starts and which direction it goes in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GradientShape {
+    /// Blends along the line from `start` to `end`, and is constant across it.
+    Linear { start: Vec2, end: Vec2 },
+    /// Blends outwards in circles from `center`, reaching the last stop at `radius`.
+    Radial { center: Vec2, radius: f32 },
+}
+
+/// Colours blended across a shape, used to fill meshes with `Sprite::new_gradient_rect`
+/// and similar. Positions are in the sprite's local space, where a rect goes from -0.5
+/// to 0.5.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    pub shape: GradientShape,
+    /// The colours along the gradient, with offsets from 0 at its start to 1 at its end,
+    /// sorted by offset. Points before the first or after the last stop use its colour.
+    pub stops: Vec<(f32, Color)>,
+}
+
+impl Gradient {
+    pub fn linear(start: Vec2, end: Vec2, from: Color, to: Color) -> Self {
+        Self {
+            shape: GradientShape::Linear { start, end },
+            stops: vec![(0.0, from), (1.0, to)],
+        }
+    }
+
+    pub fn radial(center: Vec2, radius: f32, inner: Color, outer: Color) -> Self {
+        Self {
+            shape: GradientShape::Radial { center, radius },
+            stops: vec![(0.0, inner), (1.0, outer)],
+        }
+    }
+
+    /// Add a colour at `offset` along the gradient, after any stops already there.
+    pub fn with_stop(mut self, offset: f32, color: Color) -> Self {
+        let index = self.stops.partition_point(|&(stop, _)| stop <= offset);
+        self.stops.insert(index, (offset, color));
+        self
+    }
+
+    /// How far along the gradient a point is, where 0 is its start and 1 its end.
+    /// Gradients of zero length or radius are at 0 everywhere.
+    pub fn offset(&self, point: Vec2) -> f32 {
+        match self.shape {
+            GradientShape::Linear { start, end } => {
+                let direction = end - start;
+                match direction.length_squared() {
+                    length if length > 0.0 => (point - start).dot(direction) / length,
+                    _ => 0.0,
+                }
+            }
+            GradientShape::Radial { center, radius } => match radius > 0.0 {
+                true => point.distance(center) / radius,
+                false => 0.0,
+            },
+        }
+    }
+
+    /// The colour of the gradient at a point.
+    pub fn color_at(&self, point: Vec2) -> Color {
+        let offset = self.offset(point);
+        let index = self.stops.partition_point(|&(stop, _)| stop <= offset);
+        let color = match (self.stops.get(index.wrapping_sub(1)), self.stops.get(index)) {
+            (Some(&(start, from)), Some(&(end, to))) => {
+                let t = (offset - start) / (end - start);
+                vec4(from).lerp(vec4(to), t)
+            }
+            (Some(&(_, color)), None) | (None, Some(&(_, color))) => vec4(color),
+            (None, None) => Vec4::ONE,
+        };
+        Color {
+            r: color.x as f64,
+            g: color.y as f64,
+            b: color.z as f64,
+            a: color.w as f64,
+        }
+    }
+
+    /// Build a mesh covering a shape, coloured by the gradient. The shape is a polygon
+    /// that every point of can be seen from the average of its corners, such as any
+    /// convex shape. The texture coordinates stretch a texture over its bounding box,
+    /// and are 0 along any axis the box has no size in.
+    pub fn fill(&self, points: &[Vec2]) -> (Vec<ColorTextureVertex>, Vec<u32>) {
+        assert!(points.len() >= 3, "a shape needs at least three points");
+
+        let center = points.iter().sum::<Vec2>() / points.len() as f32;
+        let min = points.iter().copied().reduce(Vec2::min).unwrap();
+        let max = points.iter().copied().reduce(Vec2::max).unwrap();
+        let size = max - min;
+        let vertex = |position: Vec2| {
+            let offset = position - min;
+            let tex_coords = Vec2::new(
+                if size.x > 0.0 { offset.x / size.x } else { 0.0 },
+                if size.y > 0.0 { offset.y / size.y } else { 0.0 },
+            );
+            ColorTextureVertex {
+                position: [position.x, position.y, 0.0],
+                // Texture coordinates go down while positions go up.
+                tex_coords: [tex_coords.x, 1.0 - tex_coords.y],
+                color: vec4(self.color_at(position)).to_array(),
+            }
+        };
+
+        let edge: Vec<_> = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .flat_map(|(&a, &b)| {
+                (0..EDGE_STEPS).map(move |i| a.lerp(b, i as f32 / EDGE_STEPS as f32))
+            })
+            .collect();
+        let ring_size = edge.len() as u32;
+
+        let mut vertices = vec![vertex(center)];
+        for ring in 1..=RINGS {
+            let t = ring as f32 / RINGS as f32;
+            vertices.extend(edge.iter().map(|&point| vertex(center.lerp(point, t))));
+        }
+
+        // A fan around the centre, then a strip of quads between each pair of rings.
+        let mut indices = Vec::new();
+        for i in 0..ring_size {
+            indices.extend([0, 1 + i, 1 + (i + 1) % ring_size]);
+        }
+        for ring in 0..RINGS - 1 {
+            let inner = 1 + ring * ring_size;
+            let outer = inner + ring_size;
+            for i in 0..ring_size {
+                let next = (i + 1) % ring_size;
+                indices.extend([
+                    inner + i,
+                    outer + i,
+                    outer + next,
+                    inner + i,
+                    outer + next,
+                    inner + next,
+                ]);
+            }
+        }
+
+        (vertices, indices)
+    }
+}
+
+fn vec4(color: Color) -> Vec4 {
+    Vec4::new(
+        color.r as f32,
+        color.g as f32,
+        color.b as f32,
+        color.a as f32,
+    )
+}
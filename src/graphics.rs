@@ -1,18 +1,28 @@
+use std::collections::HashMap;
 use std::ops::Index;
+use std::sync::{Arc, Mutex};
 use std::{num::NonZeroU32, path::Path};
 
-use glam::Vec2;
-use image::{DynamicImage, GenericImageView};
+use glam::{UVec2, Vec2};
+use image::{DynamicImage, RgbaImage};
 use indexmap::IndexMap;
-use text_to_png::TextRenderer;
 use wgpu::{
-    BindGroup, BindGroupLayout, Color, Device, Queue, RenderPass, RenderPipeline, Sampler, Surface,
-    SurfaceConfiguration, TextureView,
+    BindGroup, BindGroupLayout, Buffer, Color, CommandEncoder, Device, Queue, RenderPipeline,
+    Sampler, Surface, SurfaceConfiguration, TextureView,
 };
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
-use crate::sprite::{ColorVertex, Filter, TextureVertex, Transform};
+use crate::camera::{Camera, Viewport};
+use crate::compressed::{self, CompressedImage};
+use crate::layer::{LayerCamera, LayerID, RenderLayer};
+use crate::light::{LightInstance, LitLayer};
+use crate::pipeline::{PipelineCache, StencilMode};
+use crate::rect::Rect;
+use crate::render::{ranges_by, sort_commands, DrawCommand, Instance, PixelRect, Renderer, View};
+use crate::scale::{Placement, ScaleMode};
+use crate::sprite::{self, Filter, SamplerDesc, Sprite};
+use crate::text::{self, Font, FontError, GlyphAtlas, TextMetrics};
 
 /// A unique identifier for each font stored.
 pub type FontID = usize;
@@ -28,14 +38,28 @@ pub struct Graphics {
     /// in a 640x480 window, 320,0 would be on the right of the window, but if frame_size
     /// was Some(Vec2::new(2.0, 2.0)), then the right side of the window would be 1,0.
     pub frame_size: Option<Vec2>,
+    /// How the frame is fitted into the window when `frame_size` is set.
+    pub scale_mode: ScaleMode,
+    /// The camera used by every layer that follows the world.
+    pub camera: Camera,
+    /// The layers sprites are drawn on, in the order they are drawn. Every `Graphics`
+    /// starts with the background, world, effects and UI layers.
+    pub layers: Vec<RenderLayer>,
+    /// If this isn't empty, the layers that use a camera are drawn once for each viewport
+    /// using the viewport's camera instead of `camera`. Layers without a camera are still
+    /// drawn over the whole window.
+    pub viewports: Vec<Viewport>,
 
-    pub fonts: IndexMap<FontID, TextRenderer>,
+    pub fonts: IndexMap<FontID, Font>,
     next_font: FontID,
+    pub(crate) glyph_atlas: GlyphAtlas,
+    /// Holds the distance fields of glyphs drawn by text with `TextStyle::sdf`.
+    pub(crate) sdf_atlas: GlyphAtlas,
     pub texture_manager: TextureManager,
-    depth_texture: TextureView,
-
-    color_pipeline: RenderPipeline,
-    texture_pipeline: RenderPipeline,
+    pub(crate) renderer: Renderer,
+    /// The rect shared by textured sprites, so that they can be batched.
+    pub(crate) quad_vertices: Arc<Buffer>,
+    pub(crate) quad_indices: Arc<Buffer>,
     /// The color used to clear the screen every frame. Black by default.
     pub background_color: Color,
 }
@@ -59,7 +83,9 @@ impl Graphics {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: wgpu::Features::empty(),
+                    // Block compressed textures are used when they can be, and decoded
+                    // on the CPU otherwise.
+                    features: adapter.features() & wgpu::Features::TEXTURE_COMPRESSION_BC,
                     limits: wgpu::Limits::default(),
                 },
                 None,
@@ -76,114 +102,11 @@ impl Graphics {
         };
         surface.configure(&device, &config);
 
-        let texture_manager = TextureManager::new(&device, &queue);
-        let depth_texture = Self::make_depth_texture(&device, &config);
-
-        let color_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("color_shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("color.wgsl").into()),
-        });
-
-        let color_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[],
-                push_constant_ranges: &[],
-            });
-
-        let color_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("color_pipeline"),
-            layout: Some(&color_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &color_shader,
-                entry_point: "vertex_main",
-                buffers: &[ColorVertex::desc(), Transform::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &color_shader,
-                entry_point: "fragment_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
-
-        let texture_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("texture_shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("texture.wgsl").into()),
-        });
-
-        let texture_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&texture_manager.bind_group_layout],
-                push_constant_ranges: &[],
-            });
-
-        let texture_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("texture_pipeline"),
-            layout: Some(&texture_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &texture_shader,
-                entry_point: "vertex_main",
-                buffers: &[TextureVertex::desc(), Transform::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &texture_shader,
-                entry_point: "fragment_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        let mut texture_manager = TextureManager::new(&device, &queue);
+        let glyph_atlas = GlyphAtlas::new(&device, &mut texture_manager, false);
+        let sdf_atlas = GlyphAtlas::new(&device, &mut texture_manager, true);
+        let renderer = Renderer::new(&device, &config, &texture_manager);
+        let (quad_vertices, quad_indices) = sprite::unit_quad(&device);
 
         Self {
             device,
@@ -191,39 +114,25 @@ impl Graphics {
             surface,
             config,
             frame_size: None,
+            scale_mode: ScaleMode::default(),
+            camera: Camera::default(),
+            layers: RenderLayer::defaults(),
+            viewports: Vec::new(),
 
             fonts: IndexMap::new(),
             next_font: 0,
+            glyph_atlas,
+            sdf_atlas,
             texture_manager,
-            depth_texture,
-
-            color_pipeline,
-            texture_pipeline,
+            renderer,
+            quad_vertices,
+            quad_indices,
             background_color: Color::BLACK,
         }
     }
 
-    fn make_depth_texture(device: &Device, config: &SurfaceConfiguration) -> TextureView {
-        let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
-            depth_or_array_layers: 1,
-        };
-
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("depth_texture"),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-        });
-
-        texture.create_view(&wgpu::TextureViewDescriptor::default())
-    }
-
     pub(crate) fn render<F: FnMut(Frame)>(&mut self, mut function: F) {
+        self.texture_manager.remove_dropped();
         let output = self.surface.get_current_texture().unwrap();
         let view = output
             .texture
@@ -233,38 +142,32 @@ impl Graphics {
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        {
-            let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("render_pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.background_color),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
-            });
-
-            let frame = Frame {
-                frame_size: self.frame_size.unwrap_or_else(|| self.get_window_size()),
-                texture_manager: &self.texture_manager,
-                render_pass,
-                color_pipeline: &self.color_pipeline,
-                texture_pipeline: &self.texture_pipeline,
-                queue: &self.queue,
-            };
-
-            function(frame);
-        }
+        let placement = self
+            .scale_mode
+            .placement(self.frame_size, self.get_window_size());
+        function(Frame {
+            frame_size: placement.frame_size,
+            texture_manager: &self.texture_manager,
+            queue: &self.queue,
+            device: &self.device,
+            encoder: &mut encoder,
+            view: &view,
+            background_color: self.background_color,
+            camera: self.camera,
+            layers: &self.layers,
+            viewports: &self.viewports,
+            renderer: &mut self.renderer,
+            target_size: self.scale_mode.target_size(&placement),
+            offscreen: self.scale_mode.is_offscreen(self.frame_size),
+            placement,
+            commands: Vec::new(),
+            instances: Vec::new(),
+            lights: Vec::new(),
+            occluders: Vec::new(),
+            clip_rects: Vec::new(),
+            masks: Vec::new(),
+            group: 0,
+        });
 
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
@@ -274,7 +177,7 @@ impl Graphics {
         self.config.width = size.width;
         self.config.height = size.height;
         self.surface.configure(&self.device, &self.config);
-        self.depth_texture = Self::make_depth_texture(&self.device, &self.config);
+        self.renderer.resize(&self.device, &self.config);
     }
 
     /// Get the size of the window
@@ -282,15 +185,65 @@ impl Graphics {
         glam::UVec2::new(self.config.width, self.config.height).as_vec2()
     }
 
-    /// Get the size of the renderable frame
+    /// Get the size of the renderable frame, which can be bigger than `frame_size` when
+    /// using `ScaleMode::Expand`.
     pub fn get_frame_size(&self) -> Vec2 {
-        self.frame_size.unwrap_or_else(|| self.get_window_size())
+        self.placement().frame_size
+    }
+
+    /// Convert a position in the window, in pixels from the top left, to frame
+    /// coordinates.
+    pub fn window_to_frame(&self, position: Vec2) -> Vec2 {
+        self.placement().window_to_frame(position)
+    }
+
+    fn placement(&self) -> Placement {
+        self.scale_mode
+            .placement(self.frame_size, self.get_window_size())
     }
 
     /// Load a font from the true type font at `path`.
     pub fn load_font<T: AsRef<Path>>(&mut self, path: T) -> FontID {
         let contents = std::fs::read(path).unwrap();
-        let font = TextRenderer::try_new_with_ttf_font_data(&contents).unwrap();
+        self.add_font(Font::from_bytes(&contents))
+    }
+
+    /// Load an AngelCode BMFont from its `.fnt` file, along with the pages it uses.
+    pub fn load_bmfont<T: AsRef<Path>>(&mut self, path: T) -> Result<FontID, FontError> {
+        Ok(self.add_font(Font::load_bmfont(path)?))
+    }
+
+    /// Load a bitmap font from the image at `path`, which is split into a grid of
+    /// `cell_size` pixel cells holding each of `characters` in order, row by row.
+    pub fn load_grid_font<T: AsRef<Path>>(
+        &mut self,
+        path: T,
+        cell_size: UVec2,
+        characters: &str,
+    ) -> Result<FontID, FontError> {
+        let image = image::open(path)?;
+        Ok(self.add_font(Font::from_grid(&image, cell_size, characters)?))
+    }
+
+    /// Find the size of `text` and where its lines and characters are, without drawing
+    /// it. If `max_width` is `Some`, lines are wrapped to fit in it like `Text` does.
+    pub fn measure_text(
+        &self,
+        font: FontID,
+        text: &str,
+        size: f32,
+        max_width: Option<f32>,
+    ) -> TextMetrics {
+        text::measure(&self.fonts, font, text, size, max_width)
+    }
+
+    /// Set the fonts that are used, in order, for characters that `font` doesn't have.
+    pub fn set_fallbacks(&mut self, font: FontID, fallbacks: &[FontID]) {
+        self.fonts[&font].fallbacks = fallbacks.to_vec();
+    }
+
+    /// Add a font that has already been loaded.
+    pub fn add_font(&mut self, font: Font) -> FontID {
         self.fonts.insert(self.next_font, font);
         self.next_font += 1;
         self.next_font - 1
@@ -300,37 +253,530 @@ impl Graphics {
     pub fn clear_fonts(&mut self) {
         self.fonts.clear();
         self.next_font = 0;
+        self.glyph_atlas.clear();
+        self.sdf_atlas.clear();
+    }
+
+    /// Add a layer that will be drawn on top of all the current layers.
+    pub fn add_layer(&mut self, layer: RenderLayer) -> LayerID {
+        self.layers.push(layer);
+        self.layers.len() - 1
+    }
+
+    /// Find the layer called `name`.
+    pub fn layer_id(&self, name: &str) -> Option<LayerID> {
+        self.layers.iter().position(|layer| layer.name == name)
     }
 }
 
-/// A handle for structures that are needed during rendering itself.
+/// A handle for structures that are needed during rendering itself. Sprites rendered
+/// to the frame are queued up and drawn once the frame is dropped, layer by layer in
+/// the order given by each layer's `SortMode`.
 pub struct Frame<'a> {
-    /// The size of the window or frame if `Graphics::frame_size` is some.
+    /// The size of the visible part of the frame, see `Graphics::get_frame_size`.
     pub frame_size: Vec2,
     pub texture_manager: &'a TextureManager,
-    pub render_pass: RenderPass<'a>,
     pub queue: &'a Queue,
-    pub(crate) color_pipeline: &'a RenderPipeline,
-    pub(crate) texture_pipeline: &'a RenderPipeline,
+    pub(crate) device: &'a Device,
+    encoder: &'a mut CommandEncoder,
+    view: &'a TextureView,
+    background_color: Color,
+    camera: Camera,
+    layers: &'a [RenderLayer],
+    viewports: &'a [Viewport],
+    renderer: &'a mut Renderer,
+    /// The size in pixels of the texture being drawn to.
+    target_size: Vec2,
+    /// If the frame is drawn to an offscreen target before being copied into the window.
+    offscreen: bool,
+    placement: Placement,
+    commands: Vec<DrawCommand<'a>>,
+    instances: Vec<Instance>,
+    lights: Vec<(LayerID, LightInstance)>,
+    /// The edges of every occluder, from their start to their end.
+    occluders: Vec<(LayerID, [f32; 4])>,
+    clip_rects: Vec<Rect>,
+    /// The masks that are currently applied, so they can be removed again.
+    masks: Vec<DrawCommand<'a>>,
+    group: u32,
+}
+
+impl<'a> Frame<'a> {
+    /// Queue a draw call to be executed once the frame is finished.
+    pub(crate) fn push(&mut self, mut command: DrawCommand<'a>, instance: Instance) {
+        let masks = self.mask_count(command.layer);
+        command.instance = self.instances.len() as u32;
+        command.clip = self.clip_rects.last().map(|rect| self.scissor_rect(rect));
+        command.stencil = match masks {
+            0 => StencilMode::Ignore,
+            _ => StencilMode::Test,
+        };
+        command.stencil_ref = masks;
+        command.group = self.group;
+        self.commands.push(command);
+        self.instances.push(instance);
+    }
+
+    pub(crate) fn push_light(&mut self, layer: LayerID, light: LightInstance) {
+        self.lights.push((layer, light));
+    }
+
+    pub(crate) fn push_occluder(&mut self, layer: LayerID, edge: [f32; 4]) {
+        self.occluders.push((layer, edge));
+    }
+
+    /// Limit everything rendered after this to `rect`, until `pop_clip_rect` is called.
+    /// The rectangle is in frame coordinates, the same as sprites on a layer with no
+    /// camera. If a clip rect is already applied only the area inside both is drawn.
+    pub fn push_clip_rect(&mut self, rect: Rect) {
+        let rect = match self.clip_rects.last() {
+            Some(last) => last.intersection(&rect),
+            None => rect,
+        };
+        self.clip_rects.push(rect);
+    }
+
+    /// Remove the clip rect applied by the last call to `push_clip_rect`.
+    pub fn pop_clip_rect(&mut self) {
+        self.clip_rects.pop();
+    }
+
+    /// Use the shape of `mask` to limit where everything rendered after this is drawn,
+    /// until `pop_mask` is called. Transparent parts of a textured mask are cut out.
+    /// The mask only applies to sprites on the same layer as it. If a mask is already
+    /// applied only the area inside both is drawn.
+    pub fn push_mask(&mut self, mask: &'a Sprite) {
+        let level = self.mask_count(mask.layer);
+        self.push(mask.draw_command(self.texture_manager), mask.instance());
+
+        let command = self.commands.last_mut().unwrap();
+        command.stencil = StencilMode::Increment;
+        command.stencil_ref = level;
+        command.group = self.group + 1;
+        self.masks.push(*command);
+        self.group += 2;
+    }
+
+    /// Remove the mask applied by the last call to `push_mask`.
+    pub fn pop_mask(&mut self) {
+        if let Some(mut command) = self.masks.pop() {
+            command.stencil = StencilMode::Decrement;
+            command.stencil_ref = self.mask_count(command.layer) + 1;
+            command.group = self.group + 1;
+            self.commands.push(command);
+            self.group += 2;
+        }
+    }
+
+    /// The camera `layer` is seen through and the amount of the world it shows at a zoom
+    /// of 1. With several viewports this is the first viewport's camera.
+    pub(crate) fn layer_view(&self, layer: LayerID) -> (Camera, Vec2) {
+        let camera = self.layers.get(layer).map(|layer| layer.camera);
+        match (camera, self.viewports.first()) {
+            (Some(LayerCamera::Screen), _) => (Camera::default(), self.frame_size),
+            (Some(LayerCamera::Custom(camera)), _) => (camera, self.frame_size),
+            (_, Some(viewport)) => (viewport.camera, self.frame_size * viewport.rect.size()),
+            (_, None) => (self.camera, self.frame_size),
+        }
+    }
+
+    /// The number of masks currently applied to `layer`.
+    fn mask_count(&self, layer: LayerID) -> u32 {
+        self.masks.iter().filter(|mask| mask.layer == layer).count() as u32
+    }
+
+    /// Convert a rectangle in frame coordinates to the pixels it covers.
+    fn scissor_rect(&self, rect: &Rect) -> PixelRect {
+        let scale = self.target_size / self.frame_size;
+        let to_pixels = |point: Vec2| {
+            ((Vec2::new(point.x, -point.y) * scale + self.target_size / 2.0).round())
+                .clamp(Vec2::ZERO, self.target_size)
+                .as_uvec2()
+        };
+        let min = to_pixels(Vec2::new(rect.min.x, rect.max.y));
+        let max = to_pixels(Vec2::new(rect.max.x, rect.min.y));
+        (min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+}
+
+impl Drop for Frame<'_> {
+    fn drop(&mut self) {
+        // Layers without a camera are drawn once over the whole window, while the others
+        // are drawn through each viewport.
+        let window = View {
+            pixels: (0, 0, self.target_size.x as u32, self.target_size.y as u32),
+            frame_size: self.frame_size,
+            camera: self.camera,
+        };
+        let views: Vec<_> = match self.viewports.is_empty() {
+            true => vec![window],
+            false => self
+                .viewports
+                .iter()
+                .map(|viewport| View {
+                    pixels: viewport.pixel_rect(self.target_size),
+                    frame_size: self.frame_size * viewport.rect.size(),
+                    camera: viewport.camera,
+                })
+                .collect(),
+        };
+        let window = View {
+            camera: Camera::default(),
+            ..window
+        };
+
+        // The first camera is for layers without a camera, followed by the camera of
+        // every layer in every view.
+        let cameras: Vec<_> = std::iter::once(window.camera.matrix(window.frame_size))
+            .chain(self.layers.iter().flat_map(|layer| {
+                views
+                    .iter()
+                    .map(|view| layer.camera.matrix(&view.camera, view.frame_size))
+            }))
+            .collect();
+        self.renderer
+            .write_cameras(self.device, self.queue, &cameras);
+
+        let mut commands = std::mem::take(&mut self.commands);
+        let layers = self.layers;
+        commands
+            .retain(|command| matches!(layers.get(command.layer), Some(layer) if layer.visible));
+        for command in &commands {
+            let key = command.key(layers[command.layer].sort_mode);
+            self.renderer.pipelines.prepare(self.device, key);
+        }
+        commands.sort_by_key(|command| command.layer);
+        for range in ranges_by(&commands, |command| command.layer) {
+            let layer = commands[range.start].layer;
+            sort_commands(&mut commands[range], layers[layer].sort_mode);
+        }
+
+        // Store the instances in the order they are drawn, so that neighbouring draws can
+        // be drawn together.
+        let instances: Vec<_> = commands
+            .iter_mut()
+            .enumerate()
+            .map(|(i, command)| {
+                let instance = self.instances[command.instance as usize];
+                command.instance = i as u32;
+                instance
+            })
+            .collect();
+        self.renderer
+            .write_instances(self.device, self.queue, &instances);
+
+        // Lit layers draw every command again to their normals, in the same order.
+        let lit = |layer: LayerID| matches!(layers.get(layer), Some(layer) if layer.visible && layer.lighting.is_some());
+        let normals: Vec<_> = commands
+            .iter()
+            .filter(|command| lit(command.layer))
+            .map(|command| command.to_normals())
+            .collect();
+        for command in &normals {
+            let key = command.key(layers[command.layer].sort_mode);
+            self.renderer.pipelines.prepare(self.device, key);
+        }
+
+        // Each lit layer has its own block of occluders.
+        let lit_layers: Vec<_> = (0..layers.len()).filter(|&layer| lit(layer)).collect();
+        let mut lights = std::mem::take(&mut self.lights);
+        lights.retain(|(layer, _)| lit(*layer));
+        lights.sort_by_key(|(layer, _)| *layer);
+        let light_instances: Vec<_> = lights.iter().map(|(_, light)| *light).collect();
+        let occluders: Vec<Vec<_>> = lit_layers
+            .iter()
+            .map(|&layer| {
+                self.occluders
+                    .iter()
+                    .filter(|(l, _)| *l == layer)
+                    .map(|(_, edge)| *edge)
+                    .collect()
+            })
+            .collect();
+        if !lit_layers.is_empty() {
+            let lighting = &mut self.renderer.lighting;
+            lighting.prepare(self.device, self.target_size.as_uvec2());
+            lighting.write(self.device, self.queue, &light_instances, &occluders);
+        }
+
+        if self.offscreen {
+            self.renderer.prepare_target(
+                self.device,
+                self.texture_manager,
+                self.target_size.as_uvec2(),
+            );
+        }
+
+        let renderer = &*self.renderer;
+        let (color_view, depth_view) = renderer.attachments(self.view, self.offscreen);
+        for (i, range) in ranges_by(&commands, |command| command.layer)
+            .into_iter()
+            .enumerate()
+        {
+            let commands = &commands[range];
+            let layer = commands[0].layer;
+            let sort_mode = layers[layer].sort_mode;
+
+            // Views that round down to no pixels are skipped, as wgpu rejects empty
+            // viewports.
+            let layer_views: Vec<_> = match layers[layer].camera {
+                LayerCamera::Screen => vec![(&window, 0)],
+                _ => views
+                    .iter()
+                    .enumerate()
+                    .map(|(i, view)| (view, 1 + layer * views.len() + i))
+                    .collect(),
+            }
+            .into_iter()
+            .filter(|(view, _)| view.pixels.2 > 0 && view.pixels.3 > 0)
+            .collect();
+
+            let load = match i {
+                0 => wgpu::LoadOp::Clear(self.background_color),
+                _ => wgpu::LoadOp::Load,
+            };
+            // Lit layers are drawn on their own, then lit as they are drawn over the
+            // layers before them.
+            let (view, scene_load) = match layers[layer].lighting {
+                Some(_) => (
+                    renderer.lighting.scene(),
+                    wgpu::LoadOp::Clear(Color::TRANSPARENT),
+                ),
+                None => (color_view, load),
+            };
+
+            let mut render_pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: scene_load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: true,
+                    }),
+                }),
+            });
+
+            renderer.draw_layer(&mut render_pass, commands, sort_mode, &layer_views);
+            drop(render_pass);
+
+            if let Some(ambient) = layers[layer].lighting {
+                let start = normals.partition_point(|command| command.layer < layer);
+                let end = normals.partition_point(|command| command.layer <= layer);
+                let normals = &normals[start..end];
+                let start = lights.partition_point(|(l, _)| *l < layer);
+                let end = lights.partition_point(|(l, _)| *l <= layer);
+                renderer.draw_lighting(
+                    self.encoder,
+                    LitLayer {
+                        ambient,
+                        normals,
+                        sort_mode,
+                        lights: start as u32..end as u32,
+                        occluders: lit_layers.iter().position(|&l| l == layer).unwrap(),
+                        views: &layer_views,
+                        output: color_view,
+                        depth: depth_view,
+                        load,
+                    },
+                );
+            }
+        }
+
+        // With nothing to draw, the frame is still cleared to the background.
+        if commands.is_empty() {
+            self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("clear_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: color_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.background_color),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+        }
+
+        if self.offscreen {
+            let mut render_pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("blit_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: self.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            renderer.draw_target(&mut render_pass, &self.placement);
+        }
+    }
+}
+
+/// Why a texture couldn't be loaded.
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    /// Raw pixels whose length doesn't match the size they were given.
+    Size {
+        width: u32,
+        height: u32,
+        len: usize,
+    },
+    /// A KTX2 or DDS file that is malformed, or holds data that can't be loaded.
+    Compressed(String),
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "couldn't read the texture: {error}"),
+            Self::Image(error) => write!(f, "couldn't decode the image: {error}"),
+            Self::Size { width, height, len } => {
+                write!(f, "{len} bytes don't hold {width}x{height} RGBA pixels")
+            }
+            Self::Compressed(reason) => write!(f, "couldn't load the compressed texture: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+impl From<std::io::Error> for TextureError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
 }
 
 /// An identifier used to locate textures within a `TextureManager`'s list of textures.
-pub type TextureID = usize;
+/// Slots are reused once their texture is removed, so every ID also holds the
+/// generation of its slot, which stops stale IDs from finding the texture that
+/// replaced theirs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureID {
+    index: u32,
+    generation: u32,
+}
+
+/// A reference counted texture. The texture is removed from its `TextureManager` once
+/// every clone of the handle has been dropped, including those kept by sprites made
+/// with it.
+#[derive(Clone, Debug)]
+pub struct TextureHandle(Arc<HandleInner>);
+
+#[derive(Debug)]
+struct HandleInner {
+    id: TextureID,
+    /// The textures whose handles have all been dropped, which the texture manager
+    /// removes the next time it is used.
+    dropped: Arc<Mutex<Vec<TextureID>>>,
+}
+
+impl TextureHandle {
+    pub fn id(&self) -> TextureID {
+        self.0.id
+    }
+}
+
+impl Drop for HandleInner {
+    fn drop(&mut self) {
+        self.dropped.lock().unwrap().push(self.id);
+    }
+}
 
 /// Contains all textures and a collection of everything required for them
 pub struct TextureManager {
-    textures: IndexMap<TextureID, BindGroup>,
+    slots: Vec<Slot>,
+    /// The indices of the slots that are empty.
+    free: Vec<u32>,
+    dropped: Arc<Mutex<Vec<TextureID>>>,
     error_texture: BindGroup,
-    next_id: TextureID,
+    /// Drawn in place of missing texture arrays.
+    error_array: BindGroup,
 
-    bind_group_layout: BindGroupLayout,
-    linear_sampler: Sampler,
-    nearest_sampler: Sampler,
+    pub(crate) bind_group_layout: BindGroupLayout,
+    /// The layout of texture arrays, which have a layer for every image.
+    pub(crate) array_bind_group_layout: BindGroupLayout,
+    /// A sampler for every description that has been used, which are shared between
+    /// textures. The mutex lets bind groups be made while rendering a frame.
+    samplers: Mutex<HashMap<SamplerDesc, Sampler>>,
+    /// Draws each mipmap level from the one above it.
+    mipmap_pipeline: RenderPipeline,
+    /// Draws the mipmaps of linear textures, which aren't stored as sRGB.
+    linear_mipmap_pipeline: RenderPipeline,
+    /// If the device can sample BC compressed textures without decoding them first.
+    compression: bool,
 }
 
 impl TextureManager {
     fn new(device: &Device, queue: &Queue) -> Self {
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let bind_group_layout = Self::make_layout(device, wgpu::TextureViewDimension::D2);
+        let array_bind_group_layout =
+            Self::make_layout(device, wgpu::TextureViewDimension::D2Array);
+        let error_sampler = SamplerDesc::from(Filter::Nearest).create(device);
+
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            dropped: Arc::new(Mutex::new(Vec::new())),
+            error_texture: Self::make_error_texture(
+                device,
+                queue,
+                &error_sampler,
+                &bind_group_layout,
+                wgpu::TextureViewDimension::D2,
+            ),
+            error_array: Self::make_error_texture(
+                device,
+                queue,
+                &error_sampler,
+                &array_bind_group_layout,
+                wgpu::TextureViewDimension::D2Array,
+            ),
+            mipmap_pipeline: PipelineCache::create_blit(
+                device,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                &bind_group_layout,
+            ),
+            linear_mipmap_pipeline: PipelineCache::create_blit(
+                device,
+                wgpu::TextureFormat::Rgba8Unorm,
+                &bind_group_layout,
+            ),
+
+            bind_group_layout,
+            array_bind_group_layout,
+            samplers: Mutex::new(HashMap::new()),
+            compression: device
+                .features()
+                .contains(wgpu::Features::TEXTURE_COMPRESSION_BC),
+        }
+    }
+
+    fn make_layout(device: &Device, view_dimension: wgpu::TextureViewDimension) -> BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
             entries: &[
                 wgpu::BindGroupLayoutEntry {
@@ -338,7 +784,7 @@ impl TextureManager {
                     visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Texture {
                         sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        view_dimension: wgpu::TextureViewDimension::D2,
+                        view_dimension,
                         multisampled: false,
                     },
                     count: None,
@@ -350,44 +796,7 @@ impl TextureManager {
                     count: None,
                 },
             ],
-        });
-
-        let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: None,
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
-        let nearest_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: None,
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
-        Self {
-            textures: IndexMap::new(),
-            error_texture: Self::make_error_texture(
-                device,
-                queue,
-                &nearest_sampler,
-                &bind_group_layout,
-            ),
-            next_id: 0,
-
-            bind_group_layout,
-            linear_sampler,
-            nearest_sampler,
-        }
+        })
     }
 
     fn make_error_texture(
@@ -395,6 +804,7 @@ impl TextureManager {
         queue: &Queue,
         sampler: &Sampler,
         bind_group_layout: &BindGroupLayout,
+        dimension: wgpu::TextureViewDimension,
     ) -> BindGroup {
         let size = wgpu::Extent3d {
             width: 2,
@@ -437,9 +847,12 @@ impl TextureManager {
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(
-                        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
+                    resource: wgpu::BindingResource::TextureView(&texture.create_view(
+                        &wgpu::TextureViewDescriptor {
+                            dimension: Some(dimension),
+                            ..Default::default()
+                        },
+                    )),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
@@ -449,16 +862,175 @@ impl TextureManager {
         })
     }
 
-    /// Deletes all values in the texture cache.
+    /// Store a bind group that was made outside of the texture manager, which won't be
+    /// removed by `clear`.
+    pub(crate) fn insert_bind_group(&mut self, bind_group: BindGroup, opaque: bool) -> TextureID {
+        self.insert(Texture {
+            bind_group,
+            texture: None,
+            size: UVec2::ZERO,
+            mip_levels: 1,
+            layers: 1,
+            opaque,
+            compressed: false,
+            array: false,
+            linear: false,
+            internal: true,
+        })
+    }
+
+    /// Replace the bind group of a texture, used when a texture has to be recreated.
+    pub(crate) fn set_bind_group(&mut self, id: TextureID, bind_group: BindGroup) {
+        if let Some(texture) = self.texture_mut(id) {
+            texture.bind_group = bind_group;
+        }
+    }
+
+    /// Store a texture in the first empty slot.
+    fn insert(&mut self, texture: Texture) -> TextureID {
+        self.remove_dropped();
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.texture = Some(texture);
+                TextureID {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    texture: Some(texture),
+                });
+                TextureID {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    fn texture(&self, id: TextureID) -> Option<&Texture> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.texture.as_ref())
+    }
+
+    fn texture_mut(&mut self, id: TextureID) -> Option<&mut Texture> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.texture.as_mut())
+    }
+
+    /// Remove a texture, freeing its memory. Its ID, and every copy of it, will find
+    /// the error texture from now on. Returns false if there was no texture to remove.
+    pub fn remove(&mut self, id: TextureID) -> bool {
+        match self.texture(id) {
+            Some(texture) if !texture.internal => {}
+            _ => return false,
+        }
+
+        let slot = &mut self.slots[id.index as usize];
+        if let Some(texture) = slot.texture.take().and_then(|texture| texture.texture) {
+            texture.destroy();
+        }
+        slot.generation += 1;
+        self.free.push(id.index);
+        true
+    }
+
+    /// Remove the textures whose handles have all been dropped.
+    pub(crate) fn remove_dropped(&mut self) {
+        let dropped = std::mem::take(&mut *self.dropped.lock().unwrap());
+        for id in dropped {
+            self.remove(id);
+        }
+    }
+
+    /// Deletes all values in the texture cache, apart from the ones used internally
+    /// such as the glyph atlas. The IDs of the removed textures become stale, so they
+    /// won't find textures that are made later.
     pub fn clear(&mut self) {
-        self.textures.clear();
-        self.next_id = 0;
+        let ids = self
+            .slots
+            .iter()
+            .enumerate()
+            .map(|(index, slot)| TextureID {
+                index: index as u32,
+                generation: slot.generation,
+            })
+            .collect::<Vec<_>>();
+        for id in ids {
+            self.remove(id);
+        }
+    }
+
+    /// Check if `id` refers to a texture that hasn't been removed.
+    pub fn contains(&self, id: TextureID) -> bool {
+        self.texture(id).is_some()
     }
 
     /// Get the texture if it is available. Index into the manager if you want
     /// to get an error texture to replace missing textures.
     pub fn get(&self, id: TextureID) -> Option<&BindGroup> {
-        self.textures.get(&id)
+        self.texture(id).map(|texture| &texture.bind_group)
+    }
+
+    /// Get the bind group of a texture array, or an error texture array if there is no
+    /// texture array with that ID.
+    pub(crate) fn array(&self, id: TextureID) -> &BindGroup {
+        match self.texture(id) {
+            Some(texture) if texture.array => &texture.bind_group,
+            _ => &self.error_array,
+        }
+    }
+
+    /// Check if every pixel of the texture is fully opaque. Missing textures are
+    /// replaced by the error texture, which is opaque.
+    pub fn is_opaque(&self, id: TextureID) -> bool {
+        match self.texture(id) {
+            Some(texture) => texture.opaque,
+            None => true,
+        }
+    }
+
+    /// Create a bind group that samples `view` as described by `sampler`.
+    pub(crate) fn make_bind_group(
+        &self,
+        device: &Device,
+        view: &TextureView,
+        sampler: impl Into<SamplerDesc>,
+    ) -> BindGroup {
+        self.make_bind_group_with(device, &self.bind_group_layout, view, sampler.into())
+    }
+
+    fn make_bind_group_with(
+        &self,
+        device: &Device,
+        layout: &BindGroupLayout,
+        view: &TextureView,
+        desc: SamplerDesc,
+    ) -> BindGroup {
+        let mut samplers = self.samplers.lock().unwrap();
+        let sampler = samplers.entry(desc).or_insert_with(|| desc.create(device));
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
     }
 
     /// Create a texture from `image` and store it in the texture cache. Returns the
@@ -468,9 +1040,217 @@ impl TextureManager {
         device: &Device,
         queue: &Queue,
         image: DynamicImage,
-        filter: Filter,
+        sampler: impl Into<SamplerDesc>,
+    ) -> TextureID {
+        let texture = self.create_texture(device, queue, image, sampler, false);
+        self.insert(texture)
+    }
+
+    /// Create a texture whose pixels are used as they are, rather than as sRGB colors.
+    /// This is needed for textures holding data instead of colors, such as normal maps.
+    pub fn make_linear_texture(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        image: DynamicImage,
+        sampler: impl Into<SamplerDesc>,
     ) -> TextureID {
-        let (width, height) = image.dimensions();
+        let texture = self.create_texture(device, queue, image, sampler, true);
+        self.insert(texture)
+    }
+
+    /// Create a texture from the contents of an image file in any format the `image`
+    /// crate can read, such as one embedded with `include_bytes!`. KTX2 and DDS files
+    /// holding BC1 to BC7 data are loaded with `make_compressed_texture`.
+    pub fn make_texture_from_bytes(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        bytes: &[u8],
+        sampler: impl Into<SamplerDesc>,
+    ) -> Result<TextureID, TextureError> {
+        if CompressedImage::is_container(bytes) {
+            return self.make_compressed_texture(device, queue, bytes, sampler);
+        }
+        let image = image::load_from_memory(bytes)?;
+        Ok(self.make_texture(device, queue, image, sampler))
+    }
+
+    /// Create a texture from an image file, which can also be a KTX2 or DDS file.
+    pub fn make_texture_from_path<T: AsRef<Path>>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        path: T,
+        sampler: impl Into<SamplerDesc>,
+    ) -> Result<TextureID, TextureError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_ref().and_then(|extension| extension.to_str()) {
+            Some("ktx2" | "dds") => {
+                let bytes = std::fs::read(path)?;
+                self.make_compressed_texture(device, queue, &bytes, sampler)
+            }
+            _ => Ok(self.make_texture(device, queue, image::open(path)?, sampler)),
+        }
+    }
+
+    /// Create a texture from a KTX2 or DDS file holding BC1 to BC7 data. It stays
+    /// compressed on the GPU when the device supports it, which uses a fraction of the
+    /// memory, and is decoded on the CPU otherwise. BC6H holds HDR colors that can't be
+    /// decoded to 8 bits, so it fails on devices without BC support. Mipmaps are taken
+    /// from the file rather than generated, unless it is decoded.
+    pub fn make_compressed_texture(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        bytes: &[u8],
+        sampler: impl Into<SamplerDesc>,
+    ) -> Result<TextureID, TextureError> {
+        let image = CompressedImage::load(bytes)?;
+        if image.width.max(image.height) > device.limits().max_texture_dimension_2d {
+            return Err(TextureError::Compressed(
+                "the image is bigger than a texture can be".to_owned(),
+            ));
+        }
+        let texture = match self.compression && image.is_block_aligned() {
+            true => self.create_compressed_texture(device, queue, &image, sampler.into()),
+            // Decoded pixels are stored the same way the GPU would have read the blocks.
+            false => self.create_texture(
+                device,
+                queue,
+                DynamicImage::ImageRgba8(image.decode()?),
+                sampler,
+                !image.format.describe().srgb,
+            ),
+        };
+        Ok(self.insert(texture))
+    }
+
+    /// Create a texture from 8 bit RGBA pixels, row by row from the top left. There
+    /// must be exactly `width * height * 4` bytes, and at least one pixel.
+    pub fn make_texture_from_rgba(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+        sampler: impl Into<SamplerDesc>,
+    ) -> Result<TextureID, TextureError> {
+        let len = pixels.len();
+        let image = match RgbaImage::from_raw(width, height, pixels) {
+            Some(image) if len == width as usize * height as usize * 4 && len > 0 => image,
+            _ => return Err(TextureError::Size { width, height, len }),
+        };
+        Ok(self.make_texture(device, queue, DynamicImage::ImageRgba8(image), sampler))
+    }
+
+    /// Create a texture from `image` that is removed once every clone of the returned
+    /// handle has been dropped.
+    pub fn make_texture_handle(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        image: DynamicImage,
+        sampler: impl Into<SamplerDesc>,
+    ) -> TextureHandle {
+        let id = self.make_texture(device, queue, image, sampler);
+        self.make_handle(id)
+    }
+
+    /// Make a handle that removes a texture once every clone of it has been dropped.
+    /// The texture mustn't already have a handle.
+    pub fn make_handle(&self, id: TextureID) -> TextureHandle {
+        TextureHandle(Arc::new(HandleInner {
+            id,
+            dropped: self.dropped.clone(),
+        }))
+    }
+
+    /// Replace the image of a texture, keeping its ID so that every sprite using it
+    /// draws the new image. Returns false if there is no texture with that ID.
+    pub fn replace(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        id: TextureID,
+        image: DynamicImage,
+        sampler: impl Into<SamplerDesc>,
+    ) -> bool {
+        let linear = match self.texture(id) {
+            Some(texture) if !texture.internal => texture.linear,
+            _ => return false,
+        };
+
+        let texture = self.create_texture(device, queue, image, sampler, linear);
+        self.slots[id.index as usize].texture = Some(texture);
+        true
+    }
+
+    /// Create a texture array with a layer for every image, which must all be the same
+    /// size. `Sprite::new_array_rect` makes sprites that draw one of its layers, and
+    /// neighbouring sprites drawing the same array are drawn in a single call. There
+    /// can be at most the device's `max_texture_array_layers` images, which is at
+    /// least 256.
+    pub fn make_texture_array(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        images: Vec<DynamicImage>,
+        sampler: impl Into<SamplerDesc>,
+    ) -> TextureID {
+        let images: Vec<_> = images.into_iter().map(DynamicImage::into_rgba8).collect();
+        assert!(
+            images
+                .iter()
+                .all(|image| image.dimensions() == images[0].dimensions()),
+            "the images of a texture array must be the same size"
+        );
+        assert!(
+            (1..=device.limits().max_texture_array_layers as usize).contains(&images.len()),
+            "a texture array must have between 1 and {} images",
+            device.limits().max_texture_array_layers
+        );
+
+        let texture = self.create_layers(device, queue, images, sampler.into(), true, false);
+        self.insert(texture)
+    }
+
+    /// Linear textures store their pixels as they are, rather than as sRGB colors that
+    /// are converted to linear when sampled.
+    fn create_texture(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        image: DynamicImage,
+        sampler: impl Into<SamplerDesc>,
+        linear: bool,
+    ) -> Texture {
+        // Convert any pixel format, such as RGB, grayscale or 16 bit, to 8 bit RGBA.
+        let images = vec![image.into_rgba8()];
+        self.create_layers(device, queue, images, sampler.into(), false, linear)
+    }
+
+    /// Create a texture with a layer for every image. Arrays are sampled as texture
+    /// arrays, even if they only have one layer.
+    fn create_layers(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        images: Vec<RgbaImage>,
+        sampler: SamplerDesc,
+        array: bool,
+        linear: bool,
+    ) -> Texture {
+        let (width, height) = images[0].dimensions();
+        let layers = images.len() as u32;
+        let mip_levels = match sampler.mipmaps {
+            true => u32::BITS - width.max(height).leading_zeros(),
+            false => 1,
+        };
 
         let size = wgpu::Extent3d {
             width,
@@ -480,54 +1260,293 @@ impl TextureManager {
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
-            size,
-            mip_level_count: 1,
+            size: wgpu::Extent3d {
+                depth_or_array_layers: layers,
+                ..size
+            },
+            mip_level_count: mip_levels,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format: match linear {
+                true => wgpu::TextureFormat::Rgba8Unorm,
+                false => wgpu::TextureFormat::Rgba8UnormSrgb,
+            },
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+
+        for (layer, image) in images.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                image,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * width),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+                size,
+            );
+        }
+
+        let (layout, dimension) = match array {
+            true => (
+                &self.array_bind_group_layout,
+                wgpu::TextureViewDimension::D2Array,
+            ),
+            false => (&self.bind_group_layout, wgpu::TextureViewDimension::D2),
+        };
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(dimension),
+            ..Default::default()
+        });
+        let bind_group = self.make_bind_group_with(device, layout, &view, sampler);
+        self.write_mipmaps(device, queue, &texture, mip_levels, layers, linear);
+
+        let opaque = images
+            .iter()
+            .all(|image| image.pixels().all(|pixel| pixel[3] == u8::MAX));
+        Texture {
+            bind_group,
+            texture: Some(texture),
+            size: UVec2::new(width, height),
+            mip_levels,
+            layers,
+            opaque,
+            compressed: false,
+            array,
+            linear,
+            internal: false,
+        }
+    }
+
+    fn create_compressed_texture(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        image: &CompressedImage,
+        sampler: SamplerDesc,
+    ) -> Texture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: image.width,
+                height: image.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: image.levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: image.format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         });
 
+        let block_size = image.format.describe().block_size as u32;
+        for (level, data) in image.levels.iter().enumerate() {
+            // Levels smaller than a block are still copied as a whole block.
+            let (columns, rows) = compressed::blocks(image.width >> level, image.height >> level);
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(columns * block_size),
+                    rows_per_image: NonZeroU32::new(rows),
+                },
+                wgpu::Extent3d {
+                    width: columns * 4,
+                    height: rows * 4,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let bind_group = self.make_bind_group(
+            device,
+            &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            sampler,
+        );
+        Texture {
+            bind_group,
+            texture: Some(texture),
+            size: UVec2::new(image.width, image.height),
+            mip_levels: image.levels.len() as u32,
+            layers: 1,
+            opaque: image.is_opaque(),
+            compressed: true,
+            array: false,
+            linear: false,
+            internal: false,
+        }
+    }
+
+    /// Draw every mipmap level of each layer of `texture` from the level above it,
+    /// starting with the full size image.
+    fn write_mipmaps(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        texture: &wgpu::Texture,
+        levels: u32,
+        layers: u32,
+        linear: bool,
+    ) {
+        if levels <= 1 {
+            return;
+        }
+
+        let level_view = |level, layer| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: level,
+                mip_level_count: NonZeroU32::new(1),
+                base_array_layer: layer,
+                array_layer_count: NonZeroU32::new(1),
+                ..Default::default()
+            })
+        };
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mipmap_encoder"),
+        });
+        for (layer, level) in
+            (0..layers).flat_map(|layer| (1..levels).map(move |level| (layer, level)))
+        {
+            let source = level_view(level - 1, layer);
+            let bind_group = self.make_bind_group(device, &source, Filter::Linear);
+            let view = level_view(level, layer);
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("mipmap_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(match linear {
+                true => &self.linear_mipmap_pipeline,
+                false => &self.mipmap_pipeline,
+            });
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Regenerate a texture's mipmaps from its full size image, which is needed after
+    /// `update_region` or `update` since they only write the full size image. Returns
+    /// false if there is no texture with that ID or it is compressed.
+    pub fn generate_mipmaps(&self, device: &Device, queue: &Queue, id: TextureID) -> bool {
+        match self.texture(id) {
+            Some(Texture {
+                texture: Some(texture),
+                mip_levels,
+                layers,
+                compressed: false,
+                linear,
+                ..
+            }) => {
+                self.write_mipmaps(device, queue, texture, *mip_levels, *layers, *linear);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Write `pixels` into a texture with their top left at `x`, `y`. Returns false if
+    /// there is no texture with that ID, it is compressed or an array, or the pixels
+    /// don't fit inside it. Only the full size image is written, so textures with mipmaps need
+    /// `generate_mipmaps` after.
+    pub fn update_region(
+        &mut self,
+        queue: &Queue,
+        id: TextureID,
+        x: u32,
+        y: u32,
+        pixels: &RgbaImage,
+    ) -> bool {
+        let texture = match self.texture_mut(id) {
+            Some(texture) => texture,
+            None => return false,
+        };
+        let (width, height) = pixels.dimensions();
+        let gpu_texture = match &texture.texture {
+            Some(gpu_texture)
+                if !texture.compressed
+                    && !texture.array
+                    && x.checked_add(width)
+                        .is_some_and(|right| right <= texture.size.x)
+                    && y.checked_add(height)
+                        .is_some_and(|bottom| bottom <= texture.size.y) =>
+            {
+                gpu_texture
+            }
+            _ => return false,
+        };
+
         queue.write_texture(
             wgpu::ImageCopyTexture {
-                texture: &texture,
+                texture: gpu_texture,
                 mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
+                origin: wgpu::Origin3d { x, y, z: 0 },
                 aspect: wgpu::TextureAspect::All,
             },
-            image.as_rgba8().unwrap(),
+            pixels,
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: NonZeroU32::new(4 * width),
                 rows_per_image: NonZeroU32::new(height),
             },
-            size,
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
         );
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &self.bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(
-                        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(match filter {
-                        Filter::Linear => &self.linear_sampler,
-                        Filter::Nearest => &self.nearest_sampler,
-                    }),
-                },
-            ],
-        });
+        // Unless the whole texture is written, the rest of it isn't known, so it can only
+        // become translucent.
+        let opaque = pixels.pixels().all(|pixel| pixel[3] == u8::MAX);
+        texture.opaque = match UVec2::new(width, height) == texture.size {
+            true => opaque,
+            false => texture.opaque && opaque,
+        };
+        true
+    }
 
-        self.textures.insert(self.next_id, bind_group);
-        self.next_id += 1;
+    /// Overwrite every pixel of a texture with an image of the same size, which is
+    /// cheaper than `replace`. Returns false if there is no texture with that ID or it
+    /// is a different size.
+    pub fn update(&mut self, queue: &Queue, id: TextureID, image: &RgbaImage) -> bool {
+        match self.texture(id) {
+            Some(texture) if texture.size == UVec2::from(image.dimensions()) => {
+                self.update_region(queue, id, 0, 0, image)
+            }
+            _ => false,
+        }
+    }
 
-        self.next_id - 1
+    /// The size of a texture in pixels, if there is a texture with that ID.
+    pub fn size(&self, id: TextureID) -> Option<UVec2> {
+        self.texture(id).map(|texture| texture.size)
     }
 }
 
@@ -537,10 +1556,40 @@ impl Index<TextureID> for TextureManager {
     /// Get the texture at `index` from the texture cache. If it is missing return the
     /// error texture that is baked into the program.
     fn index(&self, index: TextureID) -> &Self::Output {
-        if let Some(texture) = self.textures.get(&index) {
-            texture
-        } else {
-            &self.error_texture
+        match self.texture(index) {
+            Some(texture) if !texture.array => &texture.bind_group,
+            _ => &self.error_texture,
         }
     }
 }
+
+/// A place in the texture manager that holds a texture, which is reused once that
+/// texture has been removed.
+struct Slot {
+    /// The number of textures that have been removed from the slot.
+    generation: u32,
+    texture: Option<Texture>,
+}
+
+struct Texture {
+    bind_group: BindGroup,
+    /// The texture the bind group samples, if it is owned by the texture manager.
+    texture: Option<wgpu::Texture>,
+    /// The size in pixels, which is zero if the texture isn't owned.
+    size: UVec2,
+    /// The number of mipmap levels, including the full size image.
+    mip_levels: u32,
+    /// The number of images in the texture, which is more than one for arrays.
+    layers: u32,
+    /// If none of the texture's pixels are transparent.
+    opaque: bool,
+    /// If the texture holds BC compressed blocks, which can't be written to or drawn
+    /// into.
+    compressed: bool,
+    /// If the texture is bound as a texture array.
+    array: bool,
+    /// If the pixels are stored as they are rather than as sRGB colors.
+    linear: bool,
+    /// If the texture is owned by the library rather than the user.
+    internal: bool,
+}
@@ -1,7 +1,6 @@
 use std::collections::HashMap;
 
 use glam::Vec2;
-use winit::dpi::PhysicalPosition;
 use winit::event::{ElementState, KeyboardInput, MouseButton, ScanCode};
 
 pub struct Input {
@@ -9,6 +8,9 @@ pub struct Input {
     buttons: HashMap<MouseButton, InputState>,
     /// The position of the mouse on the window.
     pub mouse_pos: Vec2,
+    /// The position of the mouse in frame coordinates, the same as sprites on a layer
+    /// with no camera. This takes `Graphics::scale_mode` into account.
+    pub frame_mouse_pos: Vec2,
     /// Amount of motion this update.
     pub mouse_mov: Vec2,
 }
@@ -19,6 +21,7 @@ impl Input {
             keys: HashMap::new(),
             buttons: HashMap::new(),
             mouse_pos: Vec2::ZERO,
+            frame_mouse_pos: Vec2::ZERO,
             mouse_mov: Vec2::ZERO,
         }
     }
@@ -88,10 +91,11 @@ impl Input {
         }
     }
 
-    pub(crate) fn handle_cursor(&mut self, pos: PhysicalPosition<f64>) {
+    pub(crate) fn handle_cursor(&mut self, pos: Vec2, frame_pos: Vec2) {
         let old_pos = self.mouse_pos;
-        self.mouse_pos = Vec2::new(pos.x as f32, pos.y as f32);
+        self.mouse_pos = pos;
         self.mouse_mov = self.mouse_pos - old_pos;
+        self.frame_mouse_pos = frame_pos;
     }
 }
 
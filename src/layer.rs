@@ -0,0 +1,111 @@
+use glam::{Mat4, Vec2};
+use wgpu::Color;
+
+use crate::camera::Camera;
+
+/// An index into `Graphics::layers`. Layers are drawn in the order they are stored and
+/// the depth buffer is cleared between them, so later layers always appear on top.
+pub type LayerID = usize;
+
+/// The layer for backdrops that are drawn behind everything else.
+pub const BACKGROUND_LAYER: LayerID = 0;
+/// The layer sprites are drawn to by default.
+pub const WORLD_LAYER: LayerID = 1;
+/// The layer for effects that are drawn over the world, such as particles.
+pub const EFFECTS_LAYER: LayerID = 2;
+/// The layer for interfaces, which ignores the world camera and is drawn last.
+pub const UI_LAYER: LayerID = 3;
+
+/// A group of sprites that are drawn together, with their own camera and ordering.
+pub struct RenderLayer {
+    pub name: String,
+    pub camera: LayerCamera,
+    pub sort_mode: SortMode,
+    /// If this is false nothing on the layer is drawn.
+    pub visible: bool,
+    /// The ambient light of a lit layer, which its sprites are lit by where no `Light`
+    /// reaches. Layers without it are drawn unlit.
+    pub lighting: Option<Color>,
+}
+
+impl RenderLayer {
+    /// Make a visible layer that follows the world camera and is sorted by depth.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            camera: LayerCamera::World,
+            sort_mode: SortMode::Depth,
+            visible: true,
+            lighting: None,
+        }
+    }
+
+    pub fn with_camera(mut self, camera: LayerCamera) -> Self {
+        self.camera = camera;
+        self
+    }
+
+    pub fn with_sort_mode(mut self, sort_mode: SortMode) -> Self {
+        self.sort_mode = sort_mode;
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    /// Light the layer with `Light`s, and the `ambient` light everywhere.
+    pub fn with_lighting(mut self, ambient: Color) -> Self {
+        self.lighting = Some(ambient);
+        self
+    }
+
+    /// The layers every `Graphics` starts with, matching the `*_LAYER` constants.
+    pub(crate) fn defaults() -> Vec<Self> {
+        vec![
+            Self::new("background"),
+            Self::new("world"),
+            Self::new("effects"),
+            Self::new("ui")
+                .with_camera(LayerCamera::Screen)
+                .with_sort_mode(SortMode::Submission),
+        ]
+    }
+}
+
+/// Which camera a layer is viewed through.
+#[derive(Clone, Copy, Debug)]
+pub enum LayerCamera {
+    /// Use `Graphics::camera`, so the layer moves with the world.
+    World,
+    /// Don't use a camera, so the layer stays fixed to the frame.
+    Screen,
+    /// Use a camera specific to this layer.
+    Custom(Camera),
+}
+
+impl LayerCamera {
+    pub(crate) fn matrix(&self, world: &Camera, frame_size: Vec2) -> Mat4 {
+        match self {
+            Self::World => world.matrix(frame_size),
+            Self::Screen => Camera::default().matrix(frame_size),
+            Self::Custom(camera) => camera.matrix(frame_size),
+        }
+    }
+}
+
+/// How the sprites within a layer are ordered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortMode {
+    /// Opaque sprites use the depth buffer, and transparent sprites are drawn from
+    /// back to front by their z position or sort key.
+    Depth,
+    /// Sprites are drawn in the order `render_to` was called, each one drawing over
+    /// the last regardless of depth.
+    Submission,
+    /// For top down games, sprites lower down the screen are drawn in front of those
+    /// above them. The z position still takes priority, so this only decides the order
+    /// of sprites with the same z. See `Sprite::y_sort_offset`.
+    Y,
+}
@@ -1,13 +1,26 @@
 pub mod audio;
+mod bitmap_font;
+pub mod camera;
+mod compressed;
+pub mod gradient;
 pub mod graphics;
 pub mod input;
+pub mod layer;
+pub mod light;
+pub mod parallax;
+pub mod particles;
+mod pipeline;
 pub mod random;
+pub mod rect;
+mod render;
+pub mod scale;
 pub mod sprite;
+pub mod text;
 
 use std::time::{Duration, Instant};
 
 use audio::Audio;
-use glam::UVec2;
+use glam::{UVec2, Vec2};
 use graphics::{Frame, Graphics};
 use winit::dpi::PhysicalSize;
 use winit::event::{Event, WindowEvent};
@@ -114,6 +127,8 @@ where
                 Event::MainEventsCleared => {
                     game.update(&mut game_data);
                     input.update();
+                    // The frame can move under the mouse when the scale mode changes.
+                    input.frame_mouse_pos = graphics.window_to_frame(input.mouse_pos);
                     window.request_redraw();
                     last_update = Instant::now();
                 }
@@ -126,14 +141,21 @@ where
                             *control_flow = ControlFlow::Exit;
                         }
                     }
-                    WindowEvent::CursorMoved { position, .. } => input.handle_cursor(position),
+                    WindowEvent::CursorMoved { position, .. } => {
+                        let position = Vec2::new(position.x as f32, position.y as f32);
+                        input.handle_cursor(position, graphics.window_to_frame(position))
+                    }
                     WindowEvent::KeyboardInput { input: event, .. } => input.handle_key(event),
                     WindowEvent::MouseInput { button, state, .. } => {
                         input.handle_button(button, state)
                     }
-                    WindowEvent::Resized(size) => graphics.resize(size),
+                    WindowEvent::Resized(size) => {
+                        graphics.resize(size);
+                        input.frame_mouse_pos = graphics.window_to_frame(input.mouse_pos);
+                    }
                     WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        graphics.resize(*new_inner_size)
+                        graphics.resize(*new_inner_size);
+                        input.frame_mouse_pos = graphics.window_to_frame(input.mouse_pos);
                     }
                     _ => {}
                 },
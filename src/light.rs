@@ -0,0 +1,657 @@
+use std::f32::consts::PI;
+use std::mem::size_of;
+use std::num::NonZeroU64;
+use std::ops::Range;
+
+use glam::{UVec2, Vec2};
+use wgpu::{
+    BindGroup, BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState, Buffer,
+    Color, CommandEncoder, Device, Queue, RenderPipeline, TextureFormat, TextureView,
+    VertexAttribute, VertexBufferLayout,
+};
+
+use crate::graphics::Frame;
+use crate::layer::{LayerID, SortMode, WORLD_LAYER};
+use crate::render::{DrawCommand, Renderer, View};
+
+/// The format normal mapped sprites draw their normals in, which lights read to shade
+/// them.
+pub(crate) const NORMAL_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+/// The format lights are added up in, which can go over 1 where bright lights overlap.
+const LIGHT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// The number of occluder edges that cast shadows on each layer, any more are ignored.
+pub const MAX_OCCLUDER_EDGES: usize = 256;
+/// The size of one layer's occluders in the occluder buffer, a count followed by the
+/// edges.
+const OCCLUDER_SIZE: u64 = 16 + MAX_OCCLUDER_EDGES as u64 * 16;
+/// The distance between each layer's occluders, which has to be a multiple of 256 bytes
+/// to be used as a dynamic offset.
+const OCCLUDER_STRIDE: u64 = OCCLUDER_SIZE.next_multiple_of(256);
+
+/// A point or spot light that brightens sprites on a layer with `RenderLayer::lighting`
+/// set. Like sprites, lights are queued every frame with `render_to`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub position: Vec2,
+    /// How far above the sprites the light is, which changes how steeply it shines on
+    /// normal mapped sprites.
+    pub height: f32,
+    /// How far the light reaches.
+    pub radius: f32,
+    pub color: Color,
+    /// Multiplies the color, and can go over 1 for bright lights.
+    pub intensity: f32,
+    /// How quickly the light fades with distance. 1 fades evenly to the radius, and higher
+    /// powers fade faster near the light.
+    pub falloff: f32,
+    /// The direction a spot light points in, in radians counterclockwise from the right.
+    pub direction: f32,
+    /// Half the width of a spot light's cone in radians, PI or more for a point light.
+    pub angle: f32,
+    /// The fraction of the cone that fades out towards its edge.
+    pub softness: f32,
+    /// If occluders on the light's layer cast shadows.
+    pub shadows: bool,
+    /// The radius of the light's source. Bigger sources cast softer shadows.
+    pub size: f32,
+    /// The layer in `Graphics::layers` the light shines on.
+    pub layer: LayerID,
+}
+
+impl Light {
+    /// A light shining in every direction, on the world layer.
+    pub fn point(position: Vec2, radius: f32, color: Color) -> Self {
+        Self {
+            position,
+            height: radius / 4.0,
+            radius,
+            color,
+            intensity: 1.0,
+            falloff: 2.0,
+            direction: 0.0,
+            angle: PI,
+            softness: 0.2,
+            shadows: true,
+            size: radius / 20.0,
+            layer: WORLD_LAYER,
+        }
+    }
+
+    /// A light shining in a cone `angle` radians either side of `direction`.
+    pub fn spot(position: Vec2, radius: f32, color: Color, direction: f32, angle: f32) -> Self {
+        Self {
+            direction,
+            angle,
+            ..Self::point(position, radius, color)
+        }
+    }
+
+    pub fn with_height(mut self, height: f32) -> Self {
+        self.height = height;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_falloff(mut self, falloff: f32) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    pub fn with_softness(mut self, softness: f32) -> Self {
+        self.softness = softness;
+        self
+    }
+
+    pub fn with_shadows(mut self, shadows: bool) -> Self {
+        self.shadows = shadows;
+        self
+    }
+
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: LayerID) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Queue the light to shine on its layer once the frame is finished. Lights on layers
+    /// without lighting are ignored.
+    pub fn render_to(&self, frame: &mut Frame) {
+        let cone = match self.angle >= PI {
+            // Past -1, so that even the point behind the light is lit.
+            true => [-2.0, -1.0],
+            false => [self.angle.cos(), (self.angle * (1.0 - self.softness)).cos()],
+        };
+        frame.push_light(
+            self.layer,
+            LightInstance {
+                position: [self.position.x, self.position.y, self.height],
+                radius: self.radius,
+                color: [
+                    self.color.r as f32 * self.intensity,
+                    self.color.g as f32 * self.intensity,
+                    self.color.b as f32 * self.intensity,
+                    1.0,
+                ],
+                direction: [self.direction.cos(), self.direction.sin()],
+                cone,
+                falloff: self.falloff,
+                size: self.size,
+                shadows: self.shadows as u32 as f32,
+            },
+        );
+    }
+}
+
+/// A shape that blocks light and casts shadows on a layer with lighting. Sprites inside
+/// an occluder are only lit by lights inside it too.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Occluder {
+    /// The corners of the shape in the world, in order around it.
+    pub points: Vec<Vec2>,
+    /// The layer in `Graphics::layers` the occluder casts shadows on.
+    pub layer: LayerID,
+}
+
+impl Occluder {
+    pub fn new(points: Vec<Vec2>) -> Self {
+        Self {
+            points,
+            layer: WORLD_LAYER,
+        }
+    }
+
+    /// A rectangle centred on `position`.
+    pub fn rect(position: Vec2, size: Vec2) -> Self {
+        let half = size / 2.0;
+        Self::new(vec![
+            position - half,
+            position + Vec2::new(half.x, -half.y),
+            position + half,
+            position + Vec2::new(-half.x, half.y),
+        ])
+    }
+
+    pub fn with_layer(mut self, layer: LayerID) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Queue the occluder to cast shadows once the frame is finished. Each layer can
+    /// have at most `MAX_OCCLUDER_EDGES` edges, counting one for every point.
+    pub fn render_to(&self, frame: &mut Frame) {
+        let next = self.points.iter().cycle().skip(1);
+        for (a, b) in self.points.iter().zip(next) {
+            frame.push_occluder(self.layer, [a.x, a.y, b.x, b.y]);
+        }
+    }
+}
+
+/// The data each light gives to the GPU.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct LightInstance {
+    /// The position followed by the height.
+    position: [f32; 3],
+    radius: f32,
+    /// The color multiplied by the intensity.
+    color: [f32; 4],
+    /// A unit vector in the direction of the light.
+    direction: [f32; 2],
+    /// The cosines of the outer and inner angles of the cone.
+    cone: [f32; 2],
+    falloff: f32,
+    size: f32,
+    /// 1 if the light casts shadows, and 0 if it doesn't.
+    shadows: f32,
+}
+
+impl LightInstance {
+    fn desc<'a>() -> VertexBufferLayout<'a> {
+        VertexBufferLayout {
+            array_stride: size_of::<Self>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: size_of::<[f32; 3]>() as u64,
+                    shader_location: 1,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: size_of::<[f32; 4]>() as u64,
+                    shader_location: 2,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: size_of::<[f32; 8]>() as u64,
+                    shader_location: 3,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: size_of::<[f32; 10]>() as u64,
+                    shader_location: 4,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: size_of::<[f32; 12]>() as u64,
+                    shader_location: 5,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: size_of::<[f32; 13]>() as u64,
+                    shader_location: 6,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: size_of::<[f32; 14]>() as u64,
+                    shader_location: 7,
+                },
+            ],
+        }
+    }
+}
+
+/// Everything needed to light one layer, after its sprites have been drawn to the
+/// scene target.
+pub(crate) struct LitLayer<'a, 'b> {
+    pub ambient: Color,
+    /// The layer's draws moved to its normals, see `DrawCommand::to_normals`.
+    pub normals: &'b [DrawCommand<'a>],
+    pub sort_mode: SortMode,
+    /// The layer's lights, in those written by `LightRenderer::write`.
+    pub lights: Range<u32>,
+    /// The index of the layer's occluders in those written by `LightRenderer::write`.
+    pub occluders: usize,
+    pub views: &'b [(&'b View, usize)],
+    /// Where the lit layer is drawn, over what has already been drawn there.
+    pub output: &'a TextureView,
+    pub depth: &'a TextureView,
+    pub load: wgpu::LoadOp<Color>,
+}
+
+/// The textures lit layers are drawn through, which match the size of the frame.
+struct LightTargets {
+    size: UVec2,
+    /// The layer's sprites before they are lit.
+    scene: TextureView,
+    normals: TextureView,
+    /// The sum of the ambient light and every light.
+    light: TextureView,
+    composite_bind_group: BindGroup,
+}
+
+/// Draws lit layers, by adding up the light that reaches each pixel and multiplying the
+/// layer by it.
+pub(crate) struct LightRenderer {
+    format: TextureFormat,
+    light_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+    light_bind_group_layout: BindGroupLayout,
+    composite_bind_group_layout: BindGroupLayout,
+    targets: Option<LightTargets>,
+    /// Binds the normals and occluders, it is remade when either of them is.
+    light_bind_group: Option<BindGroup>,
+    light_buffer: Buffer,
+    light_capacity: usize,
+    occluder_buffer: Buffer,
+    occluder_capacity: usize,
+}
+
+impl LightRenderer {
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        camera_bind_group_layout: &BindGroupLayout,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+
+        let light_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("light_bind_group_layout"),
+                entries: &[
+                    texture_entry(0),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: NonZeroU64::new(OCCLUDER_SIZE),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let composite_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("light_composite_bind_group_layout"),
+                entries: &[texture_entry(0), texture_entry(1)],
+            });
+
+        let light_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("light_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("light.wgsl").into()),
+        });
+        let light_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_bind_group_layout, &light_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let additive = BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        };
+        let light_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("light_pipeline"),
+            layout: Some(&light_layout),
+            vertex: wgpu::VertexState {
+                module: &light_shader,
+                entry_point: "vertex_main",
+                buffers: &[LightInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &light_shader,
+                entry_point: "fragment_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: LIGHT_FORMAT,
+                    blend: Some(BlendState {
+                        color: additive,
+                        alpha: additive,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let composite_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("light_composite_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("light_composite.wgsl").into()),
+        });
+        let composite_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&composite_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let composite_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("light_composite_pipeline"),
+            layout: Some(&composite_layout),
+            vertex: wgpu::VertexState {
+                module: &composite_shader,
+                entry_point: "vertex_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &composite_shader,
+                entry_point: "fragment_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let light_capacity = 16;
+        let occluder_capacity = 1;
+        Self {
+            format,
+            light_pipeline,
+            composite_pipeline,
+            light_bind_group_layout,
+            composite_bind_group_layout,
+            targets: None,
+            light_bind_group: None,
+            light_buffer: Self::make_light_buffer(device, light_capacity),
+            light_capacity,
+            occluder_buffer: Self::make_occluder_buffer(device, occluder_capacity),
+            occluder_capacity,
+        }
+    }
+
+    fn make_light_buffer(device: &Device, capacity: usize) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light_buffer"),
+            size: (capacity * size_of::<LightInstance>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn make_occluder_buffer(device: &Device, capacity: usize) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("occluder_buffer"),
+            size: capacity as u64 * OCCLUDER_STRIDE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Make sure there are targets of `size` pixels to draw lit layers through.
+    pub fn prepare(&mut self, device: &Device, size: UVec2) {
+        let size = size.max(UVec2::ONE);
+        if matches!(&self.targets, Some(targets) if targets.size == size) {
+            return;
+        }
+
+        let target = |label, format| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: size.x,
+                        height: size.y,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let scene = target("light_scene_target", self.format);
+        let normals = target("light_normal_target", NORMAL_FORMAT);
+        let light = target("light_target", LIGHT_FORMAT);
+
+        let composite_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.composite_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&scene),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&light),
+                },
+            ],
+        });
+
+        self.targets = Some(LightTargets {
+            size,
+            scene,
+            normals,
+            light,
+            composite_bind_group,
+        });
+        self.light_bind_group = None;
+    }
+
+    /// Upload this frame's lights, grouped by layer, and the occluder edges of every lit
+    /// layer.
+    pub fn write(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        lights: &[LightInstance],
+        occluders: &[Vec<[f32; 4]>],
+    ) {
+        if lights.len() > self.light_capacity {
+            self.light_capacity = lights.len().next_power_of_two();
+            self.light_buffer = Self::make_light_buffer(device, self.light_capacity);
+        }
+        queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(lights));
+
+        if occluders.len() > self.occluder_capacity {
+            self.occluder_capacity = occluders.len().next_power_of_two();
+            self.occluder_buffer = Self::make_occluder_buffer(device, self.occluder_capacity);
+            self.light_bind_group = None;
+        }
+        let mut data = vec![0; occluders.len() * OCCLUDER_STRIDE as usize];
+        for (edges, chunk) in occluders
+            .iter()
+            .zip(data.chunks_mut(OCCLUDER_STRIDE as usize))
+        {
+            let edges = &edges[..edges.len().min(MAX_OCCLUDER_EDGES)];
+            chunk[..4].copy_from_slice(bytemuck::bytes_of(&(edges.len() as u32)));
+            chunk[16..16 + edges.len() * 16].copy_from_slice(bytemuck::cast_slice(edges));
+        }
+        queue.write_buffer(&self.occluder_buffer, 0, &data);
+
+        if let (None, Some(targets)) = (&self.light_bind_group, &self.targets) {
+            self.light_bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.light_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&targets.normals),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &self.occluder_buffer,
+                            offset: 0,
+                            size: NonZeroU64::new(OCCLUDER_SIZE),
+                        }),
+                    },
+                ],
+            }));
+        }
+    }
+
+    /// The texture a lit layer's sprites are drawn to before they are lit.
+    pub fn scene(&self) -> &TextureView {
+        &self.targets.as_ref().unwrap().scene
+    }
+}
+
+impl Renderer {
+    /// Light a layer whose sprites have been drawn to the scene target, then draw it
+    /// over the output. The lights and occluders must have been written, and the
+    /// targets prepared, first.
+    pub fn draw_lighting<'a>(&'a self, encoder: &mut CommandEncoder, layer: LitLayer<'a, '_>) {
+        let lighting = &self.lighting;
+        let targets = lighting.targets.as_ref().unwrap();
+
+        // Flat sprites leave the normals transparent, so lights ignore their direction.
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("normal_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &targets.normals,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(Color::TRANSPARENT),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: layer.depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: true,
+                }),
+            }),
+        });
+        self.draw_layer(
+            &mut render_pass,
+            layer.normals,
+            layer.sort_mode,
+            layer.views,
+        );
+        drop(render_pass);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("light_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &targets.light,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(layer.ambient),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        if let Some(light_bind_group) = &lighting.light_bind_group {
+            render_pass.set_pipeline(&lighting.light_pipeline);
+            render_pass.set_vertex_buffer(0, lighting.light_buffer.slice(..));
+            render_pass.set_bind_group(
+                1,
+                light_bind_group,
+                &[(layer.occluders as u64 * OCCLUDER_STRIDE) as u32],
+            );
+            for (view, camera) in layer.views {
+                let (x, y, width, height) = view.pixels;
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                self.bind_camera(&mut render_pass, *camera);
+                render_pass.draw(0..6, layer.lights.clone());
+            }
+        }
+        drop(render_pass);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("light_composite_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: layer.output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: layer.load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&lighting.composite_pipeline);
+        render_pass.set_bind_group(0, &targets.composite_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
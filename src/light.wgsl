@@ -0,0 +1,133 @@
+struct Light {
+    // The position of the light in the world followed by its height above the sprites.
+    @location(0) position: vec3<f32>,
+    @location(1) radius: f32,
+    // The color multiplied by the intensity.
+    @location(2) color: vec4<f32>,
+    @location(3) direction: vec2<f32>,
+    // The cosines of the outer and inner angles of the cone.
+    @location(4) cone: vec2<f32>,
+    @location(5) falloff: f32,
+    // The radius of the light source, which softens its shadows.
+    @location(6) size: f32,
+    @location(7) shadows: f32,
+}
+
+@group(0)@binding(0)
+var<uniform> camera: mat4x4<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world: vec2<f32>,
+    @location(1) @interpolate(flat) position: vec3<f32>,
+    @location(2) @interpolate(flat) radius: f32,
+    @location(3) @interpolate(flat) color: vec4<f32>,
+    @location(4) @interpolate(flat) direction: vec2<f32>,
+    @location(5) @interpolate(flat) cone: vec2<f32>,
+    @location(6) @interpolate(flat) falloff: f32,
+    @location(7) @interpolate(flat) size: f32,
+    @location(8) @interpolate(flat) shadows: f32,
+}
+
+// Covers the circle the light reaches with a quad, without needing a vertex buffer.
+@vertex
+fn vertex_main(@builtin(vertex_index) index: u32, light: Light) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let world = light.position.xy + corners[index] * light.radius;
+
+    var output: VertexOutput;
+    output.clip_position = camera * vec4<f32>(world, 0.0, 1.0);
+    output.world = world;
+    output.position = light.position;
+    output.radius = light.radius;
+    output.color = light.color;
+    output.direction = light.direction;
+    output.cone = light.cone;
+    output.falloff = light.falloff;
+    output.size = light.size;
+    output.shadows = light.shadows;
+    return output;
+}
+
+struct Occluders {
+    count: u32,
+    // The start of each edge followed by its end.
+    edges: array<vec4<f32>, 256>,
+}
+
+@group(1)@binding(0)
+var normals: texture_2d<f32>;
+@group(1)@binding(1)
+var<uniform> occluders: Occluders;
+
+fn cross2(a: vec2<f32>, b: vec2<f32>) -> f32 {
+    return a.x * b.y - a.y * b.x;
+}
+
+// If the line from `start` to `end` crosses any occluder's edge.
+fn blocked(start: vec2<f32>, end: vec2<f32>) -> bool {
+    let ray = end - start;
+    for (var i = 0u; i < occluders.count; i = i + 1u) {
+        let a = occluders.edges[i].xy;
+        let edge = occluders.edges[i].zw - a;
+        let denominator = cross2(ray, edge);
+        if (abs(denominator) < 0.000001) {
+            continue;
+        }
+        let t = cross2(a - start, edge) / denominator;
+        let u = cross2(a - start, ray) / denominator;
+        // Edges touching the start don't count, so sprites aren't shadowed by the edge
+        // of the occluder they are drawn on.
+        if (t > 0.001 && t < 1.0 && u >= 0.0 && u <= 1.0) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// The fraction of the light source that can be seen from `world`, found by testing
+// points across it.
+fn visibility(world: vec2<f32>, light: vec2<f32>, size: f32) -> f32 {
+    let to_light = light - world;
+    let across = normalize(vec2<f32>(-to_light.y, to_light.x) + vec2<f32>(0.000001, 0.0)) * size;
+    var visible = 0.0;
+    for (var i = 0; i < 5; i = i + 1) {
+        let offset = f32(i - 2) / 2.0;
+        if (!blocked(world, light + across * offset)) {
+            visible = visible + 1.0;
+        }
+    }
+    return visible / 5.0;
+}
+
+@fragment
+fn fragment_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let to_light = vertex.position.xy - vertex.world;
+    let distance = length(to_light);
+    var light = pow(clamp(1.0 - distance / vertex.radius, 0.0, 1.0), vertex.falloff);
+
+    // Spot lights fade out between the inner and outer angles of their cone.
+    let from_light = -to_light / max(distance, 0.0001);
+    let spread = max(vertex.cone.y - vertex.cone.x, 0.0001);
+    light = light * clamp((dot(from_light, vertex.direction) - vertex.cone.x) / spread, 0.0, 1.0);
+
+    // Sprites with normal maps are brighter where they face the light, everything else
+    // is lit as if it faced the camera.
+    let normal = textureLoad(normals, vec2<i32>(vertex.clip_position.xy), 0);
+    if (normal.a > 0.5) {
+        let direction = normalize(vec3<f32>(to_light, vertex.position.z));
+        light = light * max(dot(normalize(normal.xyz * 2.0 - 1.0), direction), 0.0);
+    }
+
+    if (light > 0.0 && vertex.shadows > 0.5) {
+        light = light * visibility(vertex.world, vertex.position.xy, vertex.size);
+    }
+    return vec4<f32>(vertex.color.rgb * light, 1.0);
+}
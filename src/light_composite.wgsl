@@ -0,0 +1,20 @@
+// Covers the viewport with a single triangle, without needing a vertex buffer.
+@vertex
+fn vertex_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(corner * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+}
+
+@group(0)@binding(0)
+var scene: texture_2d<f32>;
+@group(0)@binding(1)
+var light: texture_2d<f32>;
+
+// The scene was drawn over a transparent target, so its color is premultiplied by its
+// alpha.
+@fragment
+fn fragment_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let color = textureLoad(scene, pixel, 0);
+    return vec4<f32>(color.rgb * textureLoad(light, pixel, 0).rgb, color.a);
+}
@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use glam::{Vec2, Vec3};
+
+use crate::graphics::{Frame, Graphics, TextureHandle, TextureID};
+use crate::layer::{LayerID, BACKGROUND_LAYER};
+use crate::render::Instance;
+use crate::sprite::{SamplerDesc, Sprite, Transform};
+
+/// A background that moves at a fraction of the camera's speed and can tile forever
+/// along either axis, used to give side scrollers a sense of depth. It is drawn as a
+/// single quad covering the view, so its texture's sampler should repeat along the
+/// axes that tile.
+pub struct ParallaxLayer {
+    sprite: Sprite,
+    /// The size of one copy of the texture in the world.
+    pub tile_size: Vec2,
+    /// How far the layer moves compared to the camera on each axis. 0 keeps it fixed to
+    /// the screen as if it were infinitely far away, and 1 moves it with the world.
+    pub scroll_factor: Vec2,
+    /// If copies of the texture are placed side by side forever.
+    pub repeat_x: bool,
+    /// If copies of the texture are placed on top of each other forever.
+    pub repeat_y: bool,
+    /// The position of the texture, which can be changed over time to make it drift.
+    pub offset: Vec2,
+    /// The depth of the layer, layers further back should have a lower z.
+    pub z: f32,
+}
+
+impl ParallaxLayer {
+    /// Make a layer that tiles horizontally and moves at half the camera's speed.
+    pub fn new(graphics: &Graphics, texture: TextureID, tile_size: Vec2) -> Self {
+        Self::from_sprite(Sprite::new_texture_rect(graphics, texture), tile_size)
+    }
+
+    /// Make a layer from a reference counted texture, which is kept alive until the
+    /// layer is dropped.
+    pub fn new_handle(graphics: &Graphics, handle: TextureHandle, tile_size: Vec2) -> Self {
+        Self::from_sprite(Sprite::new_handle_rect(graphics, handle), tile_size)
+    }
+
+    pub fn new_path<T: AsRef<Path>>(
+        graphics: &mut Graphics,
+        path: T,
+        sampler: impl Into<SamplerDesc>,
+        tile_size: Vec2,
+    ) -> Self {
+        Self::from_sprite(Sprite::new_path_rect(graphics, path, sampler), tile_size)
+    }
+
+    fn from_sprite(sprite: Sprite, tile_size: Vec2) -> Self {
+        Self {
+            sprite: sprite.with_layer(BACKGROUND_LAYER),
+            tile_size,
+            scroll_factor: Vec2::splat(0.5),
+            repeat_x: true,
+            repeat_y: false,
+            offset: Vec2::ZERO,
+            z: 0.0,
+        }
+    }
+
+    pub fn with_scroll_factor(mut self, scroll_factor: Vec2) -> Self {
+        self.scroll_factor = scroll_factor;
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat_x: bool, repeat_y: bool) -> Self {
+        self.repeat_x = repeat_x;
+        self.repeat_y = repeat_y;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_z(mut self, z: f32) -> Self {
+        self.z = z;
+        self
+    }
+
+    /// Draw the layer on a different layer than `BACKGROUND_LAYER`.
+    pub fn with_layer(mut self, layer: LayerID) -> Self {
+        self.sprite.layer = layer;
+        self
+    }
+
+    /// The layer in `Graphics::layers` the parallax layer is drawn on.
+    pub fn layer(&self) -> LayerID {
+        self.sprite.layer
+    }
+
+    /// Queue the layer to be drawn once the frame is finished, placed around the
+    /// camera of the layer it is drawn on.
+    pub fn render_to<'a>(&'a self, frame: &mut Frame<'a>) {
+        let (camera, frame_size) = frame.layer_view(self.sprite.layer);
+        let mut visible = frame_size / camera.zoom;
+        if camera.rotation != 0.0 {
+            // Cover every part of the view however it is turned.
+            visible = Vec2::splat(visible.length());
+        }
+
+        // Where the centre of the first copy of the texture appears in the world.
+        let origin = camera.position * (1.0 - self.scroll_factor) + self.offset;
+        let (x, width, u, u_scale) = axis(
+            self.repeat_x,
+            camera.position.x,
+            visible.x,
+            origin.x,
+            self.tile_size.x,
+        );
+        // Texture coordinates go down while the world goes up.
+        let (y, height, v, v_scale) = axis(
+            self.repeat_y,
+            -camera.position.y,
+            visible.y,
+            -origin.y,
+            self.tile_size.y,
+        );
+
+        let transform = Transform::default()
+            .with_translation(Vec3::new(x, -y, self.z))
+            .with_scale(Vec2::new(width, height));
+        frame.push(
+            self.sprite.draw_command(frame.texture_manager),
+            Instance {
+                matrix: transform.matrix(),
+                uv: [u, v, u_scale, v_scale],
+                ..self.sprite.instance()
+            },
+        );
+    }
+}
+
+/// The centre and size of the quad along one axis, followed by the offset and scale
+/// of its texture coordinates. Repeating axes cover the view, the others are one copy.
+fn axis(repeat: bool, camera: f32, visible: f32, origin: f32, tile: f32) -> (f32, f32, f32, f32) {
+    match repeat {
+        true => {
+            let start = (camera - visible / 2.0 - origin) / tile + 0.5;
+            // Mirrored textures only repeat every two copies.
+            (camera, visible, start.rem_euclid(2.0), visible / tile)
+        }
+        false => (origin, tile, 0.0, 1.0),
+    }
+}
@@ -0,0 +1,410 @@
+use std::ops::Range;
+use std::time::Duration;
+
+use glam::{Quat, UVec2, Vec2, Vec3};
+use wgpu::Color;
+
+use crate::graphics::{Frame, Graphics, TextureHandle, TextureID};
+use crate::layer::LayerID;
+use crate::random::Noise;
+use crate::render::Instance;
+use crate::sprite::{BlendMode, Sprite, Transform};
+
+/// Where new particles appear, around the emitter's position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmissionShape {
+    Point,
+    /// Anywhere inside a circle.
+    Circle {
+        radius: f32,
+    },
+    /// Anywhere inside a rectangle centred on the emitter.
+    Box {
+        size: Vec2,
+    },
+}
+
+/// A value that can be blended by a `Curve`.
+pub trait Lerp: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Vec2 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Vec2::lerp(self, other, t)
+    }
+}
+
+impl Lerp for Color {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        let t = t as f64;
+        Color {
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a + (other.a - self.a) * t,
+        }
+    }
+}
+
+/// A value that changes over a particle's life, blended between keys. Times go from 0
+/// when the particle is spawned to 1 when it dies.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve<T> {
+    /// The values at each time, sorted by time. Times before the first or after the last
+    /// key use its value. There must be at least one key.
+    pub keys: Vec<(f32, T)>,
+}
+
+impl<T: Lerp> Curve<T> {
+    /// A curve going from `start` to `end` over the particle's life.
+    pub fn new(start: T, end: T) -> Self {
+        Self {
+            keys: vec![(0.0, start), (1.0, end)],
+        }
+    }
+
+    pub fn constant(value: T) -> Self {
+        Self {
+            keys: vec![(0.0, value)],
+        }
+    }
+
+    /// Add a value at `time`, after any keys already there.
+    pub fn with_key(mut self, time: f32, value: T) -> Self {
+        let index = self.keys.partition_point(|&(key, _)| key <= time);
+        self.keys.insert(index, (time, value));
+        self
+    }
+
+    pub fn sample(&self, time: f32) -> T {
+        let index = self.keys.partition_point(|&(key, _)| key <= time);
+        match (self.keys.get(index.wrapping_sub(1)), self.keys.get(index)) {
+            (Some(&(start, from)), Some(&(end, to))) => {
+                from.lerp(to, (time - start) / (end - start))
+            }
+            (Some(&(_, value)), None) | (None, Some(&(_, value))) => value,
+            (None, None) => panic!("a curve needs at least one key"),
+        }
+    }
+}
+
+struct Particle {
+    position: Vec2,
+    velocity: Vec2,
+    /// The rotation it was spawned with, which `ParticleEmitter::rotation` is added to.
+    rotation: f32,
+    age: f32,
+    lifetime: f32,
+}
+
+/// Spawns particles over time or in bursts and moves them until they die. Particles are
+/// simulated in the world, so they stay where they were spawned when the emitter moves.
+/// Every particle of an emitter is drawn in a single call.
+pub struct ParticleEmitter {
+    sprite: Sprite,
+    particles: Vec<Particle>,
+    noise: Noise,
+    /// The part of a particle left over from the last update, so that low spawn rates
+    /// still spawn particles.
+    spawn_remainder: f32,
+
+    /// Where particles are spawned. Its z is used to sort the particles with sprites.
+    pub position: Vec3,
+    /// If particles are spawned over time, bursts are spawned either way.
+    pub emitting: bool,
+    /// The number of particles spawned every second.
+    pub rate: f32,
+    /// Particles aren't spawned while there are this many alive.
+    pub max_particles: usize,
+    pub shape: EmissionShape,
+    /// How long each particle lives in seconds, picked randomly in the range.
+    pub lifetime: Range<f32>,
+    /// The angle particles are launched at, in radians counterclockwise from the right.
+    pub direction: f32,
+    /// How far from `direction` particles can be launched, in radians either side. PI
+    /// launches them in every direction.
+    pub spread: f32,
+    /// The speed particles are launched at, picked randomly in the range.
+    pub speed: Range<f32>,
+    /// Added to the velocity of every particle each second.
+    pub gravity: Vec2,
+    /// How quickly particles slow down, as the fraction of their speed lost each second.
+    pub drag: f32,
+    pub size: Curve<Vec2>,
+    /// Multiplies the color of the particle's texture.
+    pub color: Curve<Color>,
+    /// The rotation particles are spawned with, picked randomly in the range.
+    pub start_rotation: Range<f32>,
+    /// Added to each particle's starting rotation over its life.
+    pub rotation: Curve<f32>,
+    /// The number of columns and rows of frames in the texture, if it is a sprite sheet.
+    pub sheet: UVec2,
+    /// The number of frames in the sprite sheet, which are played once over each
+    /// particle's life from left to right and top to bottom.
+    pub frames: u32,
+}
+
+impl ParticleEmitter {
+    /// An emitter of white squares, which can be colored with `with_color`.
+    pub fn new(graphics: &Graphics) -> Self {
+        Self::from_sprite(Sprite::new_color_rect(graphics, Color::WHITE))
+    }
+
+    pub fn new_texture(graphics: &Graphics, texture: TextureID) -> Self {
+        Self::from_sprite(Sprite::new_texture_rect(graphics, texture))
+    }
+
+    /// An emitter of a reference counted texture, which is kept alive until the emitter
+    /// is dropped.
+    pub fn new_handle(graphics: &Graphics, handle: TextureHandle) -> Self {
+        Self::from_sprite(Sprite::new_handle_rect(graphics, handle))
+    }
+
+    fn from_sprite(sprite: Sprite) -> Self {
+        Self {
+            sprite,
+            particles: Vec::new(),
+            noise: Noise::new(),
+            spawn_remainder: 0.0,
+
+            position: Vec3::ZERO,
+            emitting: true,
+            rate: 10.0,
+            max_particles: 1000,
+            shape: EmissionShape::Point,
+            lifetime: 1.0..1.0,
+            direction: std::f32::consts::FRAC_PI_2,
+            spread: 0.0,
+            speed: 100.0..100.0,
+            gravity: Vec2::ZERO,
+            drag: 0.0,
+            size: Curve::constant(Vec2::splat(8.0)),
+            color: Curve::constant(Color::WHITE),
+            start_rotation: 0.0..0.0,
+            rotation: Curve::constant(0.0),
+            sheet: UVec2::ONE,
+            frames: 1,
+        }
+    }
+
+    /// Use a fixed seed for the random variation between particles, so that the same
+    /// updates always give the same particles.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.noise = Noise::from_seed(seed);
+        self
+    }
+
+    pub fn with_position(mut self, position: Vec3) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn with_emitting(mut self, emitting: bool) -> Self {
+        self.emitting = emitting;
+        self
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    pub fn with_max_particles(mut self, max_particles: usize) -> Self {
+        self.max_particles = max_particles;
+        self
+    }
+
+    pub fn with_shape(mut self, shape: EmissionShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    pub fn with_lifetime(mut self, lifetime: Range<f32>) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// Launch particles in a cone `spread` radians either side of `direction`.
+    pub fn with_cone(mut self, direction: f32, spread: f32) -> Self {
+        self.direction = direction;
+        self.spread = spread;
+        self
+    }
+
+    pub fn with_speed(mut self, speed: Range<f32>) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_gravity(mut self, gravity: Vec2) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    pub fn with_drag(mut self, drag: f32) -> Self {
+        self.drag = drag;
+        self
+    }
+
+    pub fn with_size(mut self, size: Curve<Vec2>) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_color(mut self, color: Curve<Color>) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_rotation(mut self, start_rotation: Range<f32>, rotation: Curve<f32>) -> Self {
+        self.start_rotation = start_rotation;
+        self.rotation = rotation;
+        self
+    }
+
+    /// Play `frames` frames of a sprite sheet with `columns` and `rows` of frames over
+    /// each particle's life. A sheet is always at least one frame across and down.
+    pub fn with_sheet(mut self, columns: u32, rows: u32, frames: u32) -> Self {
+        self.sheet = UVec2::new(columns, rows);
+        self.frames = frames;
+        self
+    }
+
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.sprite.blend_mode = blend_mode;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: LayerID) -> Self {
+        self.sprite.layer = layer;
+        self
+    }
+
+    /// The layer in `Graphics::layers` the particles are drawn on.
+    pub fn layer(&self) -> LayerID {
+        self.sprite.layer
+    }
+
+    /// The number of particles alive.
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Remove every particle.
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.spawn_remainder = 0.0;
+    }
+
+    /// Spawn `count` particles at once, even if the emitter isn't emitting.
+    pub fn burst(&mut self, count: usize) {
+        let count = count.min(self.max_particles.saturating_sub(self.particles.len()));
+        for _ in 0..count {
+            let particle = self.spawn();
+            self.particles.push(particle);
+        }
+    }
+
+    /// Move every particle, remove the ones that have died and spawn new ones, usually
+    /// called every frame with `GameData::delta_time`.
+    pub fn update(&mut self, delta_time: Duration) {
+        let delta = delta_time.as_secs_f32();
+        self.particles.retain_mut(|particle| {
+            particle.age += delta;
+            particle.velocity += self.gravity * delta;
+            particle.velocity *= (1.0 - self.drag).max(0.0).powf(delta);
+            particle.position += particle.velocity * delta;
+            particle.age < particle.lifetime
+        });
+
+        if self.emitting {
+            self.spawn_remainder += self.rate * delta;
+            let count = self.spawn_remainder as usize;
+            self.spawn_remainder -= count as f32;
+            self.burst(count);
+        }
+    }
+
+    /// Queue every particle to be drawn once the frame is finished.
+    pub fn render_to<'a>(&'a self, frame: &mut Frame<'a>) {
+        let mut command = self.sprite.draw_command(frame.texture_manager);
+        // Every particle has the same sort keys, so they stay together and are drawn
+        // in one call.
+        command.transparent = true;
+        command.sort_key = self.position.z;
+        command.sort_y = self.position.y;
+
+        let sheet = self.sheet.max(UVec2::ONE);
+        let frames = self.frames.clamp(1, sheet.x * sheet.y);
+        let frame_size = Vec2::ONE / sheet.as_vec2();
+        for particle in &self.particles {
+            let time = particle.age / particle.lifetime.max(f32::EPSILON);
+            let index = ((time * frames as f32) as u32).min(frames - 1);
+            let offset = UVec2::new(index % sheet.x, index / sheet.x).as_vec2() * frame_size;
+            let color = self.color.sample(time);
+
+            let transform = Transform {
+                translation: particle.position.extend(self.position.z),
+                rotation: Quat::from_rotation_z(particle.rotation + self.rotation.sample(time)),
+                scale: self.size.sample(time),
+            };
+            frame.push(
+                command,
+                Instance {
+                    matrix: transform.matrix(),
+                    uv: [offset.x, offset.y, frame_size.x, frame_size.y],
+                    tint: [
+                        color.r as f32,
+                        color.g as f32,
+                        color.b as f32,
+                        color.a as f32,
+                    ],
+                    ..self.sprite.instance()
+                },
+            );
+        }
+    }
+
+    fn spawn(&mut self) -> Particle {
+        let offset = match self.shape {
+            EmissionShape::Point => Vec2::ZERO,
+            EmissionShape::Circle { radius } => {
+                let angle = self.random() * std::f32::consts::TAU;
+                // The square root spreads the particles evenly over the area.
+                let distance = radius * self.random().sqrt();
+                Vec2::new(angle.cos(), angle.sin()) * distance
+            }
+            EmissionShape::Box { size } => (Vec2::new(self.random(), self.random()) - 0.5) * size,
+        };
+        let angle = self.direction + (self.random() * 2.0 - 1.0) * self.spread;
+        let speed = self.random_in(self.speed.clone());
+
+        Particle {
+            position: self.position.truncate() + offset,
+            velocity: Vec2::new(angle.cos(), angle.sin()) * speed,
+            rotation: self.random_in(self.start_rotation.clone()),
+            age: 0.0,
+            lifetime: self.random_in(self.lifetime.clone()),
+        }
+    }
+
+    /// A random number from 0 to 1.
+    fn random(&mut self) -> f32 {
+        self.noise.next().unwrap_or(0) as f32 / u32::MAX as f32
+    }
+
+    fn random_in(&mut self, range: Range<f32>) -> f32 {
+        range.start.lerp(range.end, self.random())
+    }
+}
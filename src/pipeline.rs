@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+
+use wgpu::{
+    BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState, ColorWrites,
+    CompareFunction, Device, PipelineLayout, RenderPipeline, ShaderModule, StencilFaceState,
+    StencilOperation, StencilState, TextureFormat, VertexBufferLayout,
+};
+
+use crate::light::NORMAL_FORMAT;
+use crate::render::{Instance, DEPTH_FORMAT};
+use crate::sprite::{BlendMode, ColorTextureVertex, ColorVertex, TextureVertex};
+use crate::text::TextVertex;
+
+/// The shader and vertex layout a pipeline is built from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum PipelineKind {
+    Color,
+    Texture,
+    /// A texture multiplied by the colour of each vertex.
+    ColorTexture,
+    /// A layer of a texture array, chosen by each instance.
+    TextureArray,
+    Text,
+    /// Text drawn from a distance field, with its style in a uniform.
+    SdfText,
+    /// The normal map of a textured sprite, drawn to the normals of a lit layer.
+    Normal,
+}
+
+/// Everything that can differ between two variants of a pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct PipelineKey {
+    pub kind: PipelineKind,
+    /// If this draws to the normals of a lit layer rather than its colors.
+    pub normals: bool,
+    pub blend_mode: BlendMode,
+    pub depth_write: bool,
+    pub depth_compare: CompareFunction,
+    pub stencil: StencilMode,
+}
+
+/// How a pipeline uses the stencil buffer, which is used for masking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum StencilMode {
+    /// The stencil buffer is not used.
+    Ignore,
+    /// Only draw where the stencil buffer is equal to the reference.
+    Test,
+    /// Add one to the stencil buffer where it is equal to the reference, without
+    /// drawing any color.
+    Increment,
+    /// Subtract one from the stencil buffer where it is equal to the reference, without
+    /// drawing any color.
+    Decrement,
+}
+
+impl StencilMode {
+    /// If this mode is used to draw masks rather than visible sprites.
+    pub fn is_mask(self) -> bool {
+        matches!(self, Self::Increment | Self::Decrement)
+    }
+
+    fn stencil_state(self) -> StencilState {
+        let (pass_op, write_mask) = match self {
+            Self::Ignore => return StencilState::default(),
+            Self::Test => (StencilOperation::Keep, 0),
+            Self::Increment => (StencilOperation::IncrementClamp, 0xff),
+            Self::Decrement => (StencilOperation::DecrementClamp, 0xff),
+        };
+        let face = StencilFaceState {
+            compare: CompareFunction::Equal,
+            fail_op: StencilOperation::Keep,
+            depth_fail_op: StencilOperation::Keep,
+            pass_op,
+        };
+
+        StencilState {
+            front: face,
+            back: face,
+            read_mask: 0xff,
+            write_mask,
+        }
+    }
+}
+
+/// Stores every variant of the render pipelines that has been used so far, creating
+/// new ones as they are needed.
+pub(crate) struct PipelineCache {
+    format: TextureFormat,
+    color_shader: ShaderModule,
+    color_layout: PipelineLayout,
+    texture_shader: ShaderModule,
+    texture_layout: PipelineLayout,
+    color_texture_shader: ShaderModule,
+    texture_array_shader: ShaderModule,
+    texture_array_layout: PipelineLayout,
+    text_shader: ShaderModule,
+    sdf_text_shader: ShaderModule,
+    sdf_text_layout: PipelineLayout,
+    normal_shader: ShaderModule,
+    /// The layout of the uniform holding the outline, shadow and glow of SDF text.
+    pub text_style_layout: BindGroupLayout,
+    pipelines: HashMap<PipelineKey, RenderPipeline>,
+    /// Copies a texture onto the whole viewport.
+    pub blit: RenderPipeline,
+}
+
+impl PipelineCache {
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        camera_bind_group_layout: &BindGroupLayout,
+        texture_bind_group_layout: &BindGroupLayout,
+        texture_array_bind_group_layout: &BindGroupLayout,
+    ) -> Self {
+        let color_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("color_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("color.wgsl").into()),
+        });
+
+        let color_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let texture_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("texture_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("texture.wgsl").into()),
+        });
+
+        let texture_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_bind_group_layout, texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let color_texture_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("color_texture_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("color_texture.wgsl").into()),
+        });
+
+        let texture_array_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("texture_array_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("texture_array.wgsl").into()),
+        });
+
+        let texture_array_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_bind_group_layout, texture_array_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let text_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("text_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("text.wgsl").into()),
+        });
+
+        let sdf_text_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sdf_text_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("text_sdf.wgsl").into()),
+        });
+
+        let normal_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("normal_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("normal.wgsl").into()),
+        });
+
+        let text_style_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("text_style_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let sdf_text_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                camera_bind_group_layout,
+                texture_bind_group_layout,
+                &text_style_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        Self {
+            format,
+            color_shader,
+            color_layout,
+            texture_shader,
+            texture_layout,
+            color_texture_shader,
+            texture_array_shader,
+            texture_array_layout,
+            text_shader,
+            sdf_text_shader,
+            sdf_text_layout,
+            normal_shader,
+            text_style_layout,
+            pipelines: HashMap::new(),
+            blit: Self::create_blit(device, format, texture_bind_group_layout),
+        }
+    }
+
+    pub(crate) fn create_blit(
+        device: &Device,
+        format: TextureFormat,
+        texture_bind_group_layout: &BindGroupLayout,
+    ) -> RenderPipeline {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("blit_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("blit.wgsl").into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("blit_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vertex_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fragment_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    /// Make sure the pipeline matching `key` exists, so that it can be used with `get`.
+    pub fn prepare(&mut self, device: &Device, key: PipelineKey) {
+        if !self.pipelines.contains_key(&key) {
+            let pipeline = self.create(device, key);
+            self.pipelines.insert(key, pipeline);
+        }
+    }
+
+    /// Get the pipeline matching `key`, it must have been created with `prepare` first.
+    pub fn get(&self, key: PipelineKey) -> &RenderPipeline {
+        &self.pipelines[&key]
+    }
+
+    fn create(&self, device: &Device, key: PipelineKey) -> RenderPipeline {
+        let (label, shader, layout, vertex): (_, _, _, VertexBufferLayout) = match key.kind {
+            PipelineKind::Color => (
+                "color_pipeline",
+                &self.color_shader,
+                &self.color_layout,
+                ColorVertex::desc(),
+            ),
+            PipelineKind::Texture => (
+                "texture_pipeline",
+                &self.texture_shader,
+                &self.texture_layout,
+                TextureVertex::desc(),
+            ),
+            PipelineKind::ColorTexture => (
+                "color_texture_pipeline",
+                &self.color_texture_shader,
+                &self.texture_layout,
+                ColorTextureVertex::desc(),
+            ),
+            PipelineKind::TextureArray => (
+                "texture_array_pipeline",
+                &self.texture_array_shader,
+                &self.texture_array_layout,
+                TextureVertex::desc(),
+            ),
+            PipelineKind::Text => (
+                "text_pipeline",
+                &self.text_shader,
+                &self.texture_layout,
+                TextVertex::desc(),
+            ),
+            PipelineKind::SdfText => (
+                "sdf_text_pipeline",
+                &self.sdf_text_shader,
+                &self.sdf_text_layout,
+                TextVertex::desc(),
+            ),
+            PipelineKind::Normal => (
+                "normal_pipeline",
+                &self.normal_shader,
+                &self.texture_layout,
+                TextureVertex::desc(),
+            ),
+        };
+
+        // Draws without normals clear the normals they cover, unless they only tint what
+        // is under them.
+        const CLEAR: BlendComponent = BlendComponent {
+            src_factor: BlendFactor::Zero,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOperation::Add,
+        };
+        let (blend, write_mask) = match key.kind {
+            _ if key.stencil.is_mask() => (key.blend_mode.blend_state(), ColorWrites::empty()),
+            _ if !key.normals => (key.blend_mode.blend_state(), ColorWrites::ALL),
+            PipelineKind::Normal => (key.blend_mode.blend_state(), ColorWrites::ALL),
+            _ => (
+                BlendState {
+                    color: CLEAR,
+                    alpha: CLEAR,
+                },
+                match key.blend_mode {
+                    BlendMode::Alpha | BlendMode::Premultiplied => ColorWrites::ALL,
+                    _ => ColorWrites::empty(),
+                },
+            ),
+        };
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vertex_main",
+                buffers: &[vertex, Instance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: match key.stencil.is_mask() {
+                    true => "fragment_mask",
+                    false => "fragment_main",
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: match key.normals {
+                        true => NORMAL_FORMAT,
+                        false => self.format,
+                    },
+                    blend: Some(blend),
+                    write_mask,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: key.depth_write,
+                depth_compare: key.depth_compare,
+                stencil: key.stencil.stencil_state(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+}
@@ -26,7 +26,8 @@ impl Noise {
             ^ index.wrapping_pow(5)
             ^ self.seed.rotate_left(7)
             ^ index.rotate_right(1 + (self.seed % 3))
-            ^ self.seed.rotate_right(index % self.seed)
+            // A seed of 0 is treated as 1, so it doesn't divide by zero.
+            ^ self.seed.rotate_right(index % self.seed.max(1))
     }
 }
 
@@ -0,0 +1,45 @@
+use glam::Vec2;
+
+/// An axis aligned rectangle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    /// The bottom left corner.
+    pub min: Vec2,
+    /// The top right corner.
+    pub max: Vec2,
+}
+
+impl Rect {
+    pub fn new(min: Vec2, max: Vec2) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_center(center: Vec2, size: Vec2) -> Self {
+        Self {
+            min: center - size / 2.0,
+            max: center + size / 2.0,
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    pub fn center(&self) -> Vec2 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.cmpge(self.min).all() && point.cmplt(self.max).all()
+    }
+
+    /// Get the area covered by both rectangles, which will have a size of zero if they
+    /// don't overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let min = self.min.max(other.min);
+        Self {
+            min,
+            max: self.max.min(other.max).max(min),
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use std::mem::size_of;
+use std::num::NonZeroU64;
+use std::ops::Range;
+
+use glam::{Mat4, UVec2, Vec2};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, CompareFunction, Device, Queue, RenderPass,
+    SurfaceConfiguration, TextureFormat, TextureView, VertexAttribute, VertexBufferLayout,
+};
+
+use crate::camera::Camera;
+use crate::graphics::TextureManager;
+use crate::layer::{LayerID, SortMode};
+use crate::light::LightRenderer;
+use crate::pipeline::{PipelineCache, PipelineKey, PipelineKind, StencilMode};
+use crate::scale::Placement;
+use crate::sprite::{BlendMode, Filter};
+
+/// A rectangle of pixels in the window as `(x, y, width, height)`, with the origin in
+/// the top left.
+pub(crate) type PixelRect = (u32, u32, u32, u32);
+
+/// The data given to the GPU for every instance of a mesh.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct Instance {
+    pub matrix: [[f32; 4]; 4],
+    /// The offset of the texture coordinates followed by their scale.
+    pub uv: [f32; 4],
+    /// The layer of a texture array that is drawn.
+    pub layer: u32,
+    /// Multiplies the color of everything the instance draws.
+    pub tint: [f32; 4],
+}
+
+impl Instance {
+    pub fn desc<'a>() -> VertexBufferLayout<'a> {
+        VertexBufferLayout {
+            array_stride: size_of::<Self>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 2,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: size_of::<[f32; 4]>() as u64,
+                    shader_location: 3,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: size_of::<[f32; 8]>() as u64,
+                    shader_location: 4,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: size_of::<[f32; 12]>() as u64,
+                    shader_location: 5,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: size_of::<[f32; 16]>() as u64,
+                    shader_location: 7,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: size_of::<[f32; 20]>() as u64,
+                    shader_location: 8,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: size_of::<[f32; 21]>() as u64,
+                    shader_location: 9,
+                },
+            ],
+        }
+    }
+}
+
+/// The format of the depth buffer, which includes a stencil buffer for masking.
+pub(crate) const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+/// The distance between each camera in the camera buffer, which must be a multiple of
+/// the device's uniform offset alignment.
+const CAMERA_STRIDE: u64 = 256;
+
+/// A single queued draw call, recorded by `Sprite::render_to` and executed when
+/// the `Frame` is finished.
+#[derive(Clone, Copy)]
+pub(crate) struct DrawCommand<'a> {
+    pub kind: PipelineKind,
+    pub blend_mode: BlendMode,
+    pub vertex_buffer: &'a Buffer,
+    pub index_buffer: &'a Buffer,
+    pub index_count: u32,
+    pub index_format: wgpu::IndexFormat,
+    pub bind_group: Option<&'a BindGroup>,
+    /// The normal map drawn in place of this on the normals of a lit layer.
+    pub normal_map: Option<&'a BindGroup>,
+    /// If this is drawn to the normals of a lit layer rather than its colors.
+    pub normals: bool,
+    /// Extra uniforms used by some kinds of draw, such as the style of SDF text.
+    pub uniforms: Option<&'a BindGroup>,
+    /// The index of this draw's data in the frame's instance buffer.
+    pub instance: u32,
+    pub layer: LayerID,
+    /// If this is true the draw is sorted back to front and doesn't write to the
+    /// depth buffer.
+    pub transparent: bool,
+    /// Used to order transparent draws, lower keys are drawn first.
+    pub sort_key: f32,
+    /// Used to order draws in layers sorted by Y, higher values are drawn first.
+    pub sort_y: f32,
+    /// The area of the window the draw is limited to.
+    pub clip: Option<PixelRect>,
+    pub stencil: StencilMode,
+    /// The value the stencil buffer is compared against, which is the number of masks
+    /// applied to this draw.
+    pub stencil_ref: u32,
+    /// Draws are only sorted amongst others in the same group, which is changed every
+    /// time a mask is added or removed so that draws stay on the correct side of them.
+    pub group: u32,
+}
+
+impl DrawCommand<'_> {
+    /// If this can be drawn in the same call as `other`, which it must directly follow
+    /// in the instance buffer.
+    fn batches_with(&self, other: &Self, sort_mode: SortMode) -> bool {
+        self.key(sort_mode) == other.key(sort_mode)
+            && std::ptr::eq(self.vertex_buffer, other.vertex_buffer)
+            && std::ptr::eq(self.index_buffer, other.index_buffer)
+            && self.index_count == other.index_count
+            && same(self.bind_group, other.bind_group)
+            && same(self.uniforms, other.uniforms)
+            && self.clip == other.clip
+            && self.stencil_ref == other.stencil_ref
+            && self.instance + 1 == other.instance
+    }
+
+    /// The pipeline needed to draw this command on a layer with `sort_mode`.
+    /// This draw moved to the normals of a lit layer, in the same place and order.
+    /// Normal mapped sprites draw their normals, while everything else clears the
+    /// normals it covers so that hidden sprites don't shade it.
+    pub fn to_normals(self) -> Self {
+        match self.normal_map {
+            Some(normal_map) if !self.stencil.is_mask() => Self {
+                kind: PipelineKind::Normal,
+                blend_mode: BlendMode::Alpha,
+                bind_group: Some(normal_map),
+                uniforms: None,
+                normals: true,
+                ..self
+            },
+            _ => Self {
+                normals: true,
+                ..self
+            },
+        }
+    }
+
+    pub fn key(&self, sort_mode: SortMode) -> PipelineKey {
+        let (depth_write, depth_compare) = match sort_mode {
+            _ if self.stencil.is_mask() => (false, CompareFunction::Always),
+            SortMode::Depth => (!self.transparent, CompareFunction::Less),
+            SortMode::Submission => (false, CompareFunction::Always),
+            // Draws with the same depth need to pass so that the sorted order decides
+            // which is on top.
+            SortMode::Y => (!self.transparent, CompareFunction::LessEqual),
+        };
+
+        PipelineKey {
+            kind: self.kind,
+            normals: self.normals,
+            blend_mode: self.blend_mode,
+            depth_write,
+            depth_compare,
+            stencil: self.stencil,
+        }
+    }
+}
+
+/// An area of the window that layers are drawn into.
+#[derive(Clone, Copy)]
+pub(crate) struct View {
+    pub pixels: PixelRect,
+    /// The size of the part of the frame that is visible.
+    pub frame_size: Vec2,
+    pub camera: Camera,
+}
+
+/// A texture the frame is drawn to before it is copied into the window.
+struct FrameTarget {
+    size: UVec2,
+    color: TextureView,
+    depth: TextureView,
+    bind_group: BindGroup,
+}
+
+/// The render state that persists between frames.
+pub(crate) struct Renderer {
+    pub pipelines: PipelineCache,
+    pub depth_texture: TextureView,
+    format: TextureFormat,
+    target: Option<FrameTarget>,
+    instance_buffer: Buffer,
+    instance_capacity: usize,
+    camera_bind_group_layout: BindGroupLayout,
+    camera_buffer: Buffer,
+    camera_bind_group: BindGroup,
+    camera_capacity: usize,
+    pub lighting: LightRenderer,
+}
+
+impl Renderer {
+    pub fn new(
+        device: &Device,
+        config: &SurfaceConfiguration,
+        texture_manager: &TextureManager,
+    ) -> Self {
+        let instance_capacity = 64;
+        let camera_capacity = 8;
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: NonZeroU64::new(size_of::<Mat4>() as u64),
+                    },
+                    count: None,
+                }],
+            });
+        let (camera_buffer, camera_bind_group) =
+            Self::make_camera_buffer(device, &camera_bind_group_layout, camera_capacity);
+
+        Self {
+            pipelines: PipelineCache::new(
+                device,
+                config.format,
+                &camera_bind_group_layout,
+                &texture_manager.bind_group_layout,
+                &texture_manager.array_bind_group_layout,
+            ),
+            depth_texture: Self::make_depth_texture(device, config.width, config.height),
+            format: config.format,
+            target: None,
+            instance_buffer: Self::make_instance_buffer(device, instance_capacity),
+            instance_capacity,
+            lighting: LightRenderer::new(device, config.format, &camera_bind_group_layout),
+            camera_bind_group_layout,
+            camera_buffer,
+            camera_bind_group,
+            camera_capacity,
+        }
+    }
+
+    pub fn resize(&mut self, device: &Device, config: &SurfaceConfiguration) {
+        self.depth_texture = Self::make_depth_texture(device, config.width, config.height);
+    }
+
+    fn make_depth_texture(device: &Device, width: u32, height: u32) -> TextureView {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Make sure there is an offscreen target of `size` pixels to draw the frame into.
+    pub fn prepare_target(
+        &mut self,
+        device: &Device,
+        texture_manager: &TextureManager,
+        size: UVec2,
+    ) {
+        let size = size.max(UVec2::ONE);
+        if matches!(&self.target, Some(target) if target.size == size) {
+            return;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("frame_target"),
+            size: wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let color = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.target = Some(FrameTarget {
+            size,
+            depth: Self::make_depth_texture(device, size.x, size.y),
+            bind_group: texture_manager.make_bind_group(device, &color, Filter::Nearest),
+            color,
+        });
+    }
+
+    /// The color and depth textures to draw to, which are either the window's or the
+    /// offscreen target's if `offscreen` is true.
+    pub fn attachments<'a>(
+        &'a self,
+        window: &'a TextureView,
+        offscreen: bool,
+    ) -> (&'a TextureView, &'a TextureView) {
+        match &self.target {
+            Some(target) if offscreen => (&target.color, &target.depth),
+            _ => (window, &self.depth_texture),
+        }
+    }
+
+    /// Copy the offscreen target into the window at `placement`.
+    pub fn draw_target<'a>(&'a self, render_pass: &mut RenderPass<'a>, placement: &Placement) {
+        // wgpu rejects empty viewports, such as those of a minimised window.
+        if placement.size.x < 1.0 || placement.size.y < 1.0 {
+            return;
+        }
+        if let Some(target) = &self.target {
+            render_pass.set_viewport(
+                placement.position.x,
+                placement.position.y,
+                placement.size.x,
+                placement.size.y,
+                0.0,
+                1.0,
+            );
+            render_pass.set_pipeline(&self.pipelines.blit);
+            render_pass.set_bind_group(0, &target.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    fn make_instance_buffer(device: &Device, capacity: usize) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("instance_buffer"),
+            size: (capacity * size_of::<Instance>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn make_camera_buffer(
+        device: &Device,
+        layout: &BindGroupLayout,
+        capacity: usize,
+    ) -> (Buffer, BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("camera_buffer"),
+            size: capacity as u64 * CAMERA_STRIDE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(size_of::<Mat4>() as u64),
+                }),
+            }],
+        });
+
+        (buffer, bind_group)
+    }
+
+    /// Upload this frame's instances, growing the buffer if they don't fit.
+    pub fn write_instances(&mut self, device: &Device, queue: &Queue, instances: &[Instance]) {
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            self.instance_buffer = Self::make_instance_buffer(device, self.instance_capacity);
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(instances));
+    }
+
+    /// Upload this frame's cameras, which can then be bound with `bind_camera`.
+    pub fn write_cameras(&mut self, device: &Device, queue: &Queue, cameras: &[Mat4]) {
+        if cameras.len() > self.camera_capacity {
+            self.camera_capacity = cameras.len().next_power_of_two();
+            (self.camera_buffer, self.camera_bind_group) = Self::make_camera_buffer(
+                device,
+                &self.camera_bind_group_layout,
+                self.camera_capacity,
+            );
+        }
+
+        let mut data = vec![0; cameras.len() * CAMERA_STRIDE as usize];
+        for (camera, chunk) in cameras.iter().zip(data.chunks_mut(CAMERA_STRIDE as usize)) {
+            chunk[..size_of::<Mat4>()]
+                .copy_from_slice(bytemuck::cast_slice(&camera.to_cols_array()));
+        }
+        queue.write_buffer(&self.camera_buffer, 0, &data);
+    }
+
+    /// Draw with the camera at the given index of those written by `write_cameras`.
+    pub fn bind_camera<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: usize) {
+        render_pass.set_bind_group(
+            0,
+            &self.camera_bind_group,
+            &[(camera as u64 * CAMERA_STRIDE) as u32],
+        );
+    }
+
+    /// Record the draw calls of one layer, once for every view of it. Each view is drawn
+    /// using the camera at the given index of those written by `write_cameras`.
+    pub fn draw_layer<'a>(
+        &'a self,
+        render_pass: &mut RenderPass<'a>,
+        commands: &[DrawCommand<'a>],
+        sort_mode: SortMode,
+        views: &[(&View, usize)],
+    ) {
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+        for (view, camera) in views {
+            let (x, y, width, height) = view.pixels;
+            render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+            self.bind_camera(render_pass, *camera);
+
+            // Neighbouring draws of the same mesh and texture are drawn together as
+            // instances.
+            let mut start = 0;
+            while start < commands.len() {
+                let mut end = start + 1;
+                while end < commands.len()
+                    && commands[end - 1].batches_with(&commands[end], sort_mode)
+                {
+                    end += 1;
+                }
+                let command = &commands[start];
+                let instances = command.instance..command.instance + (end - start) as u32;
+                start = end;
+
+                let (x, y, width, height) = match command.clip {
+                    Some(clip) => intersect_pixels(clip, view.pixels),
+                    None => view.pixels,
+                };
+                if width == 0 || height == 0 {
+                    continue;
+                }
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_stencil_reference(command.stencil_ref);
+                render_pass.set_pipeline(self.pipelines.get(command.key(sort_mode)));
+                if let Some(bind_group) = command.bind_group {
+                    render_pass.set_bind_group(1, bind_group, &[]);
+                }
+                if let Some(uniforms) = command.uniforms {
+                    render_pass.set_bind_group(2, uniforms, &[]);
+                }
+                render_pass.set_vertex_buffer(0, command.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(command.index_buffer.slice(..), command.index_format);
+                render_pass.draw_indexed(0..command.index_count, 0, instances);
+            }
+        }
+    }
+}
+
+/// If both are the same bind group, or neither has one.
+fn same(a: Option<&BindGroup>, b: Option<&BindGroup>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => std::ptr::eq(a, b),
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+/// Get the area covered by both rectangles.
+fn intersect_pixels(a: PixelRect, b: PixelRect) -> PixelRect {
+    let x = a.0.max(b.0);
+    let y = a.1.max(b.1);
+    let right = (a.0 + a.2).min(b.0 + b.2).max(x);
+    let bottom = (a.1 + a.3).min(b.1 + b.3).max(y);
+    (x, y, right - x, bottom - y)
+}
+
+/// Sort the commands of a single layer into the order they should be drawn in.
+pub(crate) fn sort_commands(commands: &mut [DrawCommand], sort_mode: SortMode) {
+    for range in ranges_by(commands, |command| command.group) {
+        let commands = &mut commands[range];
+        match sort_mode {
+            // Opaque draws come first in the order they were submitted, followed by
+            // transparent draws from back to front.
+            SortMode::Depth => commands.sort_by(|a, b| {
+                a.transparent.cmp(&b.transparent).then_with(|| {
+                    if a.transparent {
+                        a.sort_key.total_cmp(&b.sort_key)
+                    } else {
+                        std::cmp::Ordering::Equal
+                    }
+                })
+            }),
+            SortMode::Submission => {}
+            SortMode::Y => commands.sort_by(|a, b| {
+                a.sort_key
+                    .total_cmp(&b.sort_key)
+                    .then_with(|| b.sort_y.total_cmp(&a.sort_y))
+            }),
+        }
+    }
+}
+
+/// Split commands into the ranges where `key` stays the same.
+pub(crate) fn ranges_by<T: PartialEq>(
+    commands: &[DrawCommand],
+    key: impl Fn(&DrawCommand) -> T,
+) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (i, command) in commands.iter().enumerate() {
+        match ranges.last_mut() {
+            Some(last) if key(&commands[last.start]) == key(command) => last.end = i + 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
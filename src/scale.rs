@@ -0,0 +1,86 @@
+use glam::Vec2;
+
+/// How the frame is fitted into the window when `Graphics::frame_size` is set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// Stretch the frame to fill the window, which distorts it if the window has a
+    /// different aspect ratio.
+    #[default]
+    Stretch,
+    /// Scale the frame as much as possible while keeping its aspect ratio, with black
+    /// bars filling the rest of the window.
+    Letterbox,
+    /// Draw the frame at exactly `frame_size` pixels, then scale it up by the largest
+    /// whole number that fits in the window, with black bars filling the rest. A window
+    /// smaller than the frame shrinks it like `Letterbox` instead.
+    PixelPerfect,
+    /// Keep the aspect ratio like `Letterbox`, but instead of adding bars show more of
+    /// the world in one direction so that the window is filled.
+    Expand,
+}
+
+/// Where the frame ends up in the window.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Placement {
+    /// The size of the frame that is visible, which can be bigger than the requested
+    /// frame size when expanding.
+    pub frame_size: Vec2,
+    /// The top left of the frame in the window, in pixels.
+    pub position: Vec2,
+    /// The size of the frame in the window, in pixels.
+    pub size: Vec2,
+}
+
+impl ScaleMode {
+    pub(crate) fn placement(self, frame_size: Option<Vec2>, window_size: Vec2) -> Placement {
+        let frame_size = match frame_size {
+            Some(frame_size) => frame_size,
+            None => {
+                return Placement {
+                    frame_size: window_size,
+                    position: Vec2::ZERO,
+                    size: window_size,
+                }
+            }
+        };
+
+        let fit = (window_size / frame_size).min_element();
+        let (frame_size, size) = match self {
+            Self::Stretch => (frame_size, window_size),
+            Self::Letterbox => (frame_size, (frame_size * fit).round()),
+            Self::PixelPerfect => match fit < 1.0 {
+                true => (frame_size, (frame_size.round() * fit).round()),
+                false => (frame_size, frame_size.round() * fit.floor()),
+            },
+            Self::Expand => (window_size / fit, window_size),
+        };
+
+        Placement {
+            frame_size,
+            position: ((window_size - size) / 2.0).round(),
+            size,
+        }
+    }
+
+    /// The size in pixels of the texture the frame is drawn to.
+    pub(crate) fn target_size(self, placement: &Placement) -> Vec2 {
+        match self {
+            Self::PixelPerfect => placement.frame_size.round(),
+            _ => placement.size,
+        }
+    }
+
+    /// If the frame is drawn to a texture of its own before being copied to the window.
+    pub(crate) fn is_offscreen(self, frame_size: Option<Vec2>) -> bool {
+        frame_size.is_some() && matches!(self, Self::Letterbox | Self::PixelPerfect)
+    }
+}
+
+impl Placement {
+    /// Convert a position in the window, in pixels from the top left, to frame
+    /// coordinates where 0,0 is the centre of the frame.
+    pub fn window_to_frame(&self, position: Vec2) -> Vec2 {
+        let scaled = (position - self.position - self.size / 2.0) * self.frame_size / self.size;
+        Vec2::new(scaled.x, -scaled.y)
+    }
+}
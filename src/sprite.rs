@@ -1,12 +1,23 @@
 use std::mem::size_of;
+use std::num::NonZeroU8;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 use glam::{Mat4, Quat, Vec2, Vec3};
-use image::RgbaImage;
+use image::DynamicImage;
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
-use wgpu::{Buffer, Color, VertexAttribute, VertexBufferLayout};
+use wgpu::{
+    AddressMode, BlendComponent, BlendFactor, BlendOperation, BlendState, Buffer, Color, Device,
+    FilterMode, Sampler, SamplerDescriptor, VertexAttribute, VertexBufferLayout,
+};
 
-use crate::graphics::{FontID, Frame, Graphics, TextureID};
+use crate::gradient::{self, Gradient};
+use crate::graphics::{FontID, Frame, Graphics, TextureHandle, TextureID, TextureManager};
+use crate::layer::{LayerID, WORLD_LAYER};
+use crate::pipeline::{PipelineKind, StencilMode};
+use crate::render::{DrawCommand, Instance};
+use crate::text::{self, TextStyle, TextVertex};
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -64,98 +75,280 @@ impl TextureVertex {
     }
 }
 
+/// A vertex that both samples a texture and has a colour, which the texture is
+/// multiplied by.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColorTextureVertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl ColorTextureVertex {
+    pub(crate) fn desc<'a>() -> VertexBufferLayout<'a> {
+        VertexBufferLayout {
+            array_stride: size_of::<Self>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: size_of::<[f32; 3]>() as u64,
+                    shader_location: 1,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: size_of::<[f32; 5]>() as u64,
+                    shader_location: 6,
+                },
+            ],
+        }
+    }
+}
+
 pub struct Sprite {
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
+    vertex_buffer: Arc<Buffer>,
+    index_buffer: Arc<Buffer>,
     index_count: u32,
+    index_format: wgpu::IndexFormat,
+    /// The number of bytes that can be written to the buffers without replacing them,
+    /// which is zero for buffers that can't be written to.
+    vertex_capacity: u64,
+    index_capacity: u64,
     ty: SpriteType,
+    /// Keeps the sprite's texture alive, if it is reference counted.
+    texture: Option<TextureHandle>,
+    /// If any of the sprite's vertices are partially transparent.
+    translucent: bool,
 
     pub transform: Transform,
-    pub transform_outdated: bool,
-    transform_buffer: Buffer,
+    /// How the sprite is blended with what has already been drawn.
+    pub blend_mode: BlendMode,
+    /// The layer in `Graphics::layers` the sprite is drawn on.
+    pub layer: LayerID,
+    /// Overrides the z position used to sort transparent sprites. Transparent sprites
+    /// with a lower key are drawn first.
+    pub sort_key: Option<f32>,
+    /// Added to the sprite's y position when it is on a layer sorted by Y. Setting this
+    /// to the offset from the sprite's centre to its base, usually negative half its
+    /// height, makes sprites sort by where they stand rather than their centre.
+    pub y_sort_offset: f32,
+    /// Added to the texture coordinates after scaling them, which slides the texture
+    /// across the sprite. Parts outside the texture follow its sampler's address mode.
+    pub uv_offset: Vec2,
+    /// Multiplies the texture coordinates, so a scale of 3 repeats the texture three
+    /// times across the sprite if its sampler repeats.
+    pub uv_scale: Vec2,
+    /// How far `uv_offset` moves every second when `scroll_uv` is called.
+    pub uv_scroll: Vec2,
+    /// Multiplies the colour of the whole sprite, which can fade it out or flash it a
+    /// colour without changing its mesh.
+    pub tint: Color,
+    /// A texture of the sprite's normals, which lights on a lit layer shade it with. It
+    /// is sampled the same way as the sprite's texture, so it should line up with it,
+    /// and made with `TextureManager::make_linear_texture` so its values aren't changed
+    /// by sRGB conversion. Only textured sprites use normal maps.
+    pub normal_map: Option<TextureID>,
 }
 
 impl Sprite {
-    pub fn new_color_mesh(graphics: &Graphics, vertices: &[ColorVertex], indices: &[u16]) -> Self {
-        let transform = Transform::default();
-
+    pub fn new_color_mesh<I: MeshIndex>(
+        graphics: &Graphics,
+        vertices: &[ColorVertex],
+        indices: &[I],
+    ) -> Self {
         Self {
-            vertex_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            }),
-            index_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(indices),
-                usage: wgpu::BufferUsages::INDEX,
-            }),
+            vertex_buffer: init_buffer(
+                graphics,
+                bytemuck::cast_slice(vertices),
+                wgpu::BufferUsages::VERTEX,
+            ),
+            index_buffer: init_buffer(
+                graphics,
+                bytemuck::cast_slice(indices),
+                wgpu::BufferUsages::INDEX,
+            ),
             index_count: indices.len() as u32,
+            index_format: I::FORMAT,
+            vertex_capacity: writable_size(vertices),
+            index_capacity: writable_size(indices),
             ty: SpriteType::Color,
+            texture: None,
+            translucent: vertices.iter().any(|vertex| vertex.color[3] < 1.0),
 
-            transform_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&[transform.matrix(graphics.get_window_size())]),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            }),
-            transform,
-            transform_outdated: false,
+            transform: Transform::default(),
+            blend_mode: BlendMode::default(),
+            layer: WORLD_LAYER,
+            sort_key: None,
+            y_sort_offset: 0.0,
+            uv_offset: Vec2::ZERO,
+            uv_scale: Vec2::ONE,
+            uv_scroll: Vec2::ZERO,
+            tint: Color::WHITE,
+            normal_map: None,
         }
     }
 
-    pub fn new_path_mesh<T: AsRef<Path>>(
+    pub fn new_path_mesh<T: AsRef<Path>, I: MeshIndex>(
         graphics: &mut Graphics,
         vertices: &[TextureVertex],
-        indices: &[u16],
+        indices: &[I],
         path: T,
-        filter: Filter,
+        sampler: impl Into<SamplerDesc>,
     ) -> Self {
-        let id = graphics.texture_manager.make_texture(
-            &graphics.device,
-            &graphics.queue,
-            image::open(path).unwrap(),
-            filter,
-        );
-        Self::new_texture_mesh(graphics, vertices, indices, id)
+        let id = graphics
+            .texture_manager
+            .make_texture_from_path(&graphics.device, &graphics.queue, path, sampler)
+            .unwrap();
+        let handle = graphics.texture_manager.make_handle(id);
+        Self::new_handle_mesh(graphics, vertices, indices, handle)
+    }
+
+    /// Make a mesh textured with the contents of an image file, such as one embedded
+    /// with `include_bytes!`.
+    pub fn new_bytes_mesh<I: MeshIndex>(
+        graphics: &mut Graphics,
+        vertices: &[TextureVertex],
+        indices: &[I],
+        bytes: &[u8],
+        sampler: impl Into<SamplerDesc>,
+    ) -> Self {
+        let id = graphics
+            .texture_manager
+            .make_texture_from_bytes(&graphics.device, &graphics.queue, bytes, sampler)
+            .unwrap();
+        let handle = graphics.texture_manager.make_handle(id);
+        Self::new_handle_mesh(graphics, vertices, indices, handle)
     }
 
-    pub fn new_texture_mesh(
+    /// Make a mesh from a reference counted texture, which is kept alive until the
+    /// sprite is dropped.
+    pub fn new_handle_mesh<I: MeshIndex>(
         graphics: &Graphics,
         vertices: &[TextureVertex],
-        indices: &[u16],
-        texture_id: TextureID,
+        indices: &[I],
+        handle: TextureHandle,
     ) -> Self {
-        let transform = Transform::default();
+        Self {
+            texture: Some(handle.clone()),
+            ..Self::new_texture_mesh(graphics, vertices, indices, handle.id())
+        }
+    }
 
+    pub fn new_texture_mesh<I: MeshIndex>(
+        graphics: &Graphics,
+        vertices: &[TextureVertex],
+        indices: &[I],
+        texture_id: TextureID,
+    ) -> Self {
         Self {
-            vertex_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            }),
-            index_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(indices),
-                usage: wgpu::BufferUsages::INDEX,
-            }),
+            vertex_buffer: init_buffer(
+                graphics,
+                bytemuck::cast_slice(vertices),
+                wgpu::BufferUsages::VERTEX,
+            ),
+            index_buffer: init_buffer(
+                graphics,
+                bytemuck::cast_slice(indices),
+                wgpu::BufferUsages::INDEX,
+            ),
             index_count: indices.len() as u32,
+            index_format: I::FORMAT,
+            vertex_capacity: writable_size(vertices),
+            index_capacity: writable_size(indices),
             ty: SpriteType::Texture(texture_id),
+            texture: None,
+            translucent: false,
 
-            transform_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&[transform.matrix(graphics.get_window_size())]),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            }),
-            transform,
-            transform_outdated: false,
+            transform: Transform::default(),
+            blend_mode: BlendMode::default(),
+            layer: WORLD_LAYER,
+            sort_key: None,
+            y_sort_offset: 0.0,
+            uv_offset: Vec2::ZERO,
+            uv_scale: Vec2::ONE,
+            uv_scroll: Vec2::ZERO,
+            tint: Color::WHITE,
+            normal_map: None,
         }
     }
 
-    pub fn new_color_rect(graphics: &Graphics, color: Color) -> Self {
-        let transform = Transform::default();
+    /// Make a mesh whose texture is tinted by the colour of each vertex.
+    pub fn new_color_texture_mesh<I: MeshIndex>(
+        graphics: &Graphics,
+        vertices: &[ColorTextureVertex],
+        indices: &[I],
+        texture_id: TextureID,
+    ) -> Self {
+        Self {
+            vertex_buffer: init_buffer(
+                graphics,
+                bytemuck::cast_slice(vertices),
+                wgpu::BufferUsages::VERTEX,
+            ),
+            index_buffer: init_buffer(
+                graphics,
+                bytemuck::cast_slice(indices),
+                wgpu::BufferUsages::INDEX,
+            ),
+            index_count: indices.len() as u32,
+            index_format: I::FORMAT,
+            vertex_capacity: writable_size(vertices),
+            index_capacity: writable_size(indices),
+            ty: SpriteType::ColorTexture(texture_id),
+            texture: None,
+            translucent: vertices.iter().any(MeshVertex::is_translucent),
 
+            transform: Transform::default(),
+            blend_mode: BlendMode::default(),
+            layer: WORLD_LAYER,
+            sort_key: None,
+            y_sort_offset: 0.0,
+            uv_offset: Vec2::ZERO,
+            uv_scale: Vec2::ONE,
+            uv_scroll: Vec2::ZERO,
+            tint: Color::WHITE,
+            normal_map: None,
+        }
+    }
+
+    /// Make a rect filled with a gradient.
+    pub fn new_gradient_rect(graphics: &Graphics, gradient: &Gradient) -> Self {
+        Self::new_gradient_shape(graphics, &gradient::RECT, gradient)
+    }
+
+    /// Make a shape filled with a gradient. See `Gradient::fill` for the shapes that
+    /// can be filled.
+    pub fn new_gradient_shape(graphics: &Graphics, points: &[Vec2], gradient: &Gradient) -> Self {
+        let (vertices, indices) = gradient.fill(points);
+        let vertices: Vec<_> = vertices
+            .into_iter()
+            .map(|vertex| ColorVertex {
+                position: vertex.position,
+                color: vertex.color,
+            })
+            .collect();
+        Self::new_color_mesh(graphics, &vertices, &indices)
+    }
+
+    /// Make a rect showing a texture tinted by a gradient.
+    pub fn new_texture_gradient_rect(
+        graphics: &Graphics,
+        texture_id: TextureID,
+        gradient: &Gradient,
+    ) -> Self {
+        let (vertices, indices) = gradient.fill(&gradient::RECT);
+        Self::new_color_texture_mesh(graphics, &vertices, &indices, texture_id)
+    }
+
+    pub fn new_color_rect(graphics: &Graphics, color: Color) -> Self {
         Self {
-            vertex_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
+            vertex_buffer: Arc::new(graphics.device.create_buffer_init(&BufferInitDescriptor {
                 label: None,
                 contents: bytemuck::cast_slice(&[
                     ColorVertex {
@@ -196,191 +389,529 @@ impl Sprite {
                     },
                 ]),
                 usage: wgpu::BufferUsages::VERTEX,
-            }),
-            index_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
+            })),
+            index_buffer: Arc::new(graphics.device.create_buffer_init(&BufferInitDescriptor {
                 label: None,
                 contents: bytemuck::cast_slice::<u16, u8>(&[0, 1, 2, 0, 2, 3]),
                 usage: wgpu::BufferUsages::INDEX,
-            }),
+            })),
             index_count: 6,
+            index_format: wgpu::IndexFormat::Uint16,
+            vertex_capacity: 0,
+            index_capacity: 0,
             ty: SpriteType::Color,
+            texture: None,
+            translucent: color.a < 1.0,
 
-            transform_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&[transform.matrix(graphics.get_window_size())]),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            }),
-            transform,
-            transform_outdated: false,
+            transform: Transform::default(),
+            blend_mode: BlendMode::default(),
+            layer: WORLD_LAYER,
+            sort_key: None,
+            y_sort_offset: 0.0,
+            uv_offset: Vec2::ZERO,
+            uv_scale: Vec2::ONE,
+            uv_scroll: Vec2::ZERO,
+            tint: Color::WHITE,
+            normal_map: None,
         }
     }
 
-    pub fn new_path_rect<T: AsRef<Path>>(graphics: &mut Graphics, path: T, filter: Filter) -> Self {
-        let id = graphics.texture_manager.make_texture(
-            &graphics.device,
-            &graphics.queue,
-            image::open(path).unwrap(),
-            filter,
-        );
-        Self::new_texture_rect(graphics, id)
+    pub fn new_path_rect<T: AsRef<Path>>(
+        graphics: &mut Graphics,
+        path: T,
+        sampler: impl Into<SamplerDesc>,
+    ) -> Self {
+        let id = graphics
+            .texture_manager
+            .make_texture_from_path(&graphics.device, &graphics.queue, path, sampler)
+            .unwrap();
+        let handle = graphics.texture_manager.make_handle(id);
+        Self::new_handle_rect(graphics, handle)
+    }
+
+    /// Make a rectangle from the contents of an image file, such as one embedded with
+    /// `include_bytes!`.
+    pub fn new_bytes_rect(
+        graphics: &mut Graphics,
+        bytes: &[u8],
+        sampler: impl Into<SamplerDesc>,
+    ) -> Self {
+        let id = graphics
+            .texture_manager
+            .make_texture_from_bytes(&graphics.device, &graphics.queue, bytes, sampler)
+            .unwrap();
+        let handle = graphics.texture_manager.make_handle(id);
+        Self::new_handle_rect(graphics, handle)
     }
 
+    /// Make a rectangle from a reference counted texture, which is kept alive until the
+    /// sprite is dropped.
+    pub fn new_handle_rect(graphics: &Graphics, handle: TextureHandle) -> Self {
+        Self {
+            texture: Some(handle.clone()),
+            ..Self::new_texture_rect(graphics, handle.id())
+        }
+    }
+
+    /// Draw `text` to a new texture and make a sprite of it. This makes a new texture
+    /// every time it is called, so `Text` should be used for text that changes.
     pub fn new_text_rect(
         graphics: &mut Graphics,
         font: FontID,
         text: &str,
         size: u16,
         color: Color,
-        filter: Filter,
+        sampler: impl Into<SamplerDesc>,
     ) -> Self {
-        let font = &graphics.fonts[font];
-        let color = text_to_png::Color::new(
-            (color.r * 255.0) as u8,
-            (color.g * 255.0) as u8,
-            (color.b * 255.0) as u8,
+        let image = text::render_to_image(
+            &graphics.fonts,
+            font,
+            text,
+            &TextStyle::new(size as f32, color),
+        );
+        let (width, height) = image.dimensions();
+        let handle = graphics.texture_manager.make_texture_handle(
+            &graphics.device,
+            &graphics.queue,
+            DynamicImage::ImageRgba8(image),
+            sampler,
         );
-        let pixmap = font.render_text_to_pixmap(text, size, color).unwrap();
-        let width = pixmap.size.width;
-        let height = pixmap.size.height;
-        let image = {
-            let mut image = RgbaImage::new(width + 1, height + 1);
-            for index in 0..pixmap.data.data().len() / 4 {
-                let array = [
-                    pixmap.data.data()[index * 4],
-                    pixmap.data.data()[index * 4 + 1],
-                    pixmap.data.data()[index * 4 + 2],
-                    pixmap.data.data()[index * 4 + 3],
-                ];
-                image.put_pixel(
-                    index as u32 % width,
-                    index as u32 / width,
-                    image::Rgba(array),
-                );
-            }
-            image::DynamicImage::ImageRgba8(image)
-        };
-        let id =
-            graphics
-                .texture_manager
-                .make_texture(&graphics.device, &graphics.queue, image, filter);
         let scale = Vec2::new(width as f32, height as f32)
             * (graphics.get_frame_size() / graphics.get_window_size());
 
-        Self::new_texture_rect(graphics, id).with_transform(Transform::scale(scale))
+        Self::new_handle_rect(graphics, handle).with_transform(Transform::scale(scale))
     }
 
-    pub fn new_texture_rect(graphics: &Graphics, id: TextureID) -> Self {
-        let transform = Transform::default();
+    /// A sprite whose mesh is drawn from the glyph atlas, used by `Text`.
+    pub(crate) fn new_text_mesh(
+        graphics: &Graphics,
+        vertices: &[TextVertex],
+        indices: &[u16],
+    ) -> Self {
+        let mut sprite = Self {
+            vertex_buffer: Arc::new(make_buffer(graphics, 64, wgpu::BufferUsages::VERTEX)),
+            index_buffer: Arc::new(make_buffer(graphics, 64, wgpu::BufferUsages::INDEX)),
+            index_count: 0,
+            index_format: wgpu::IndexFormat::Uint16,
+            vertex_capacity: 64,
+            index_capacity: 64,
+            ty: SpriteType::Text(graphics.glyph_atlas.id),
+            texture: None,
+            translucent: true,
 
+            transform: Transform::default(),
+            blend_mode: BlendMode::default(),
+            layer: WORLD_LAYER,
+            sort_key: None,
+            y_sort_offset: 0.0,
+            uv_offset: Vec2::ZERO,
+            uv_scale: Vec2::ONE,
+            uv_scroll: Vec2::ZERO,
+            tint: Color::WHITE,
+            normal_map: None,
+        };
+        sprite.update_mesh(graphics, vertices, indices);
+        sprite
+    }
+
+    pub fn new_texture_rect(graphics: &Graphics, id: TextureID) -> Self {
         Self {
-            vertex_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&[
-                    TextureVertex {
-                        position: [-0.5, -0.5, 0.0],
-                        tex_coords: [0.0, 1.0],
-                    },
-                    TextureVertex {
-                        position: [0.5, -0.5, 0.0],
-                        tex_coords: [1.0, 1.0],
-                    },
-                    TextureVertex {
-                        position: [0.5, 0.5, 0.0],
-                        tex_coords: [1.0, 0.0],
-                    },
-                    TextureVertex {
-                        position: [-0.5, 0.5, 0.0],
-                        tex_coords: [0.0, 0.0],
-                    },
-                ]),
-                usage: wgpu::BufferUsages::VERTEX,
-            }),
-            index_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice::<u16, u8>(&[0, 1, 2, 0, 2, 3]),
-                usage: wgpu::BufferUsages::INDEX,
-            }),
+            // Every textured rect shares the same quad, so rects drawing the same texture
+            // can be drawn together.
+            vertex_buffer: graphics.quad_vertices.clone(),
+            index_buffer: graphics.quad_indices.clone(),
             index_count: 6,
+            index_format: wgpu::IndexFormat::Uint16,
+            vertex_capacity: 0,
+            index_capacity: 0,
             ty: SpriteType::Texture(id),
+            texture: None,
+            translucent: false,
 
-            transform_buffer: graphics.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(&[transform.matrix(graphics.get_window_size())]),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            }),
-            transform,
-            transform_outdated: false,
+            transform: Transform::default(),
+            blend_mode: BlendMode::default(),
+            layer: WORLD_LAYER,
+            sort_key: None,
+            y_sort_offset: 0.0,
+            uv_offset: Vec2::ZERO,
+            uv_scale: Vec2::ONE,
+            uv_scroll: Vec2::ZERO,
+            tint: Color::WHITE,
+            normal_map: None,
+        }
+    }
+
+    /// A rect drawing one layer of a texture array made with
+    /// `TextureManager::make_texture_array`. Sprites drawing the same array one after
+    /// another are drawn in a single call, whichever layers they use.
+    pub fn new_array_rect(graphics: &Graphics, array: TextureID, layer: u32) -> Self {
+        Self {
+            ty: SpriteType::TextureArray(array, layer),
+            ..Self::new_texture_rect(graphics, array)
         }
     }
 
+    /// Change the layer of the texture array drawn by a sprite made with
+    /// `new_array_rect`. Other sprites are unaffected.
+    pub fn set_array_layer(&mut self, layer: u32) {
+        if let SpriteType::TextureArray(_, array_layer) = &mut self.ty {
+            *array_layer = layer;
+        }
+    }
+
+    pub fn with_array_layer(mut self, layer: u32) -> Self {
+        self.set_array_layer(layer);
+        self
+    }
+
+    /// The reference counted texture the sprite keeps alive, if it was made with one.
+    pub fn texture_handle(&self) -> Option<&TextureHandle> {
+        self.texture.as_ref()
+    }
+
+    /// Queue the sprite to be drawn once the frame is finished.
     pub fn render_to<'a>(&'a self, frame: &mut Frame<'a>) {
-        if self.transform_outdated {
-            frame.queue.write_buffer(
-                &self.transform_buffer,
-                0,
-                bytemuck::cast_slice(&[self.transform.matrix(frame.frame_size)]),
-            );
+        frame.push(self.draw_command(frame.texture_manager), self.instance());
+    }
+
+    /// The data the sprite gives to the GPU when it is drawn.
+    pub(crate) fn instance(&self) -> Instance {
+        Instance {
+            matrix: self.transform.matrix(),
+            uv: [
+                self.uv_offset.x,
+                self.uv_offset.y,
+                self.uv_scale.x,
+                self.uv_scale.y,
+            ],
+            layer: match self.ty {
+                SpriteType::TextureArray(_, layer) => layer,
+                _ => 0,
+            },
+            tint: [
+                self.tint.r as f32,
+                self.tint.g as f32,
+                self.tint.b as f32,
+                self.tint.a as f32,
+            ],
         }
+    }
 
-        match self.ty {
-            SpriteType::Color => {
-                frame.render_pass.set_pipeline(frame.color_pipeline);
-                frame
-                    .render_pass
-                    .set_vertex_buffer(0, self.vertex_buffer.slice(..));
-                frame
-                    .render_pass
-                    .set_vertex_buffer(1, self.transform_buffer.slice(..));
-                frame
-                    .render_pass
-                    .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                frame.render_pass.draw_indexed(0..self.index_count, 0, 0..1);
-            }
-            SpriteType::Texture(id) => {
-                frame.render_pass.set_pipeline(frame.texture_pipeline);
-                frame
-                    .render_pass
-                    .set_vertex_buffer(0, self.vertex_buffer.slice(..));
-                frame
-                    .render_pass
-                    .set_vertex_buffer(1, self.transform_buffer.slice(..));
-                frame
-                    .render_pass
-                    .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                frame
-                    .render_pass
-                    .set_bind_group(0, &frame.texture_manager[id], &[]);
-                frame.render_pass.draw_indexed(0..self.index_count, 0, 0..1);
-            }
+    pub(crate) fn draw_command<'a>(
+        &'a self,
+        texture_manager: &'a TextureManager,
+    ) -> DrawCommand<'a> {
+        let (kind, bind_group, opaque) = match self.ty {
+            SpriteType::Color => (PipelineKind::Color, None, true),
+            SpriteType::Texture(id) => (
+                PipelineKind::Texture,
+                Some(&texture_manager[id]),
+                texture_manager.is_opaque(id),
+            ),
+            SpriteType::ColorTexture(id) => (
+                PipelineKind::ColorTexture,
+                Some(&texture_manager[id]),
+                texture_manager.is_opaque(id),
+            ),
+            SpriteType::TextureArray(id, _) => (
+                PipelineKind::TextureArray,
+                Some(texture_manager.array(id)),
+                texture_manager.is_opaque(id),
+            ),
+            SpriteType::Text(id) => (PipelineKind::Text, Some(&texture_manager[id]), false),
+        };
+
+        DrawCommand {
+            kind,
+            blend_mode: self.blend_mode,
+            vertex_buffer: &self.vertex_buffer,
+            index_buffer: &self.index_buffer,
+            index_count: self.index_count,
+            index_format: self.index_format,
+            bind_group,
+            // Only textured sprites have the texture coordinates normal maps need.
+            normal_map: match (&self.ty, self.normal_map) {
+                (SpriteType::Texture(_) | SpriteType::TextureArray(..), Some(id)) => {
+                    Some(&texture_manager[id])
+                }
+                _ => None,
+            },
+            normals: false,
+            uniforms: None,
+            instance: 0,
+            layer: self.layer,
+            transparent: !opaque
+                || self.translucent
+                || self.tint.a < 1.0
+                || self.blend_mode != BlendMode::Alpha,
+            sort_key: self.sort_key.unwrap_or(self.transform.translation.z),
+            sort_y: self.transform.translation.y + self.y_sort_offset,
+            clip: None,
+            stencil: StencilMode::Ignore,
+            stencil_ref: 0,
+            group: 0,
+        }
+    }
+
+    /// Replace the sprite's vertices, which must be the type it was made with, such as
+    /// `ColorVertex` for sprites made with `new_color_mesh`. The sprite's buffers are
+    /// reused if the vertices fit, and grown otherwise, so meshes can be changed every
+    /// frame. Indices that point past the new vertices must be updated too.
+    pub fn update_vertices<V: MeshVertex>(&mut self, graphics: &Graphics, vertices: &[V]) {
+        let expected = match self.ty {
+            SpriteType::Color => size_of::<ColorVertex>(),
+            SpriteType::Texture(_) | SpriteType::TextureArray(..) => size_of::<TextureVertex>(),
+            SpriteType::ColorTexture(_) => size_of::<ColorTextureVertex>(),
+            SpriteType::Text(_) => size_of::<TextVertex>(),
+        };
+        assert_eq!(
+            size_of::<V>(),
+            expected,
+            "the vertices must have the type the sprite was made with"
+        );
+
+        write_buffer(
+            graphics,
+            &mut self.vertex_buffer,
+            &mut self.vertex_capacity,
+            bytemuck::cast_slice(vertices),
+            wgpu::BufferUsages::VERTEX,
+        );
+        if let SpriteType::Color | SpriteType::ColorTexture(_) = self.ty {
+            self.translucent = vertices.iter().any(MeshVertex::is_translucent);
         }
     }
 
+    /// Replace the sprite's indices, reusing its buffer if they fit. Either `u16` or
+    /// `u32` indices can be used whatever the sprite was made with, and `u32` is needed
+    /// for meshes with more than 65536 vertices.
+    pub fn update_indices<I: MeshIndex>(&mut self, graphics: &Graphics, indices: &[I]) {
+        write_buffer(
+            graphics,
+            &mut self.index_buffer,
+            &mut self.index_capacity,
+            bytemuck::cast_slice(indices),
+            wgpu::BufferUsages::INDEX,
+        );
+        self.index_count = indices.len() as u32;
+        self.index_format = I::FORMAT;
+    }
+
+    /// Replace the sprite's whole mesh, as `update_vertices` and `update_indices` do.
+    pub fn update_mesh<V: MeshVertex, I: MeshIndex>(
+        &mut self,
+        graphics: &Graphics,
+        vertices: &[V],
+        indices: &[I],
+    ) {
+        self.update_vertices(graphics, vertices);
+        self.update_indices(graphics, indices);
+    }
+
     pub fn set_transform(&mut self, transform: Transform) {
         self.transform = transform;
-        self.transform_outdated = true;
     }
 
     pub fn with_transform(mut self, transform: Transform) -> Self {
         self.transform = transform;
-        self.transform_outdated = true;
         self
     }
 
     pub fn with_modified_transform<F: FnOnce(Transform) -> Transform>(mut self, f: F) -> Self {
         self.transform = f(self.transform);
-        self.transform_outdated = true;
         self
     }
 
     pub fn modify_transform<F: FnOnce(Transform) -> Transform>(&mut self, f: F) {
         self.transform = f(self.transform);
-        self.transform_outdated = true;
+    }
+
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: LayerID) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_sort_key(mut self, sort_key: f32) -> Self {
+        self.sort_key = Some(sort_key);
+        self
+    }
+
+    pub fn with_y_sort_offset(mut self, y_sort_offset: f32) -> Self {
+        self.y_sort_offset = y_sort_offset;
+        self
+    }
+
+    pub fn with_uv_offset(mut self, uv_offset: Vec2) -> Self {
+        self.uv_offset = uv_offset;
+        self
+    }
+
+    pub fn with_uv_scale(mut self, uv_scale: Vec2) -> Self {
+        self.uv_scale = uv_scale;
+        self
+    }
+
+    pub fn with_uv_scroll(mut self, uv_scroll: Vec2) -> Self {
+        self.uv_scroll = uv_scroll;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_normal_map(mut self, normal_map: TextureID) -> Self {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    /// Move `uv_offset` by `uv_scroll` for the time that has passed, usually
+    /// `GameData::delta_time`. The offset wraps around so it never loses precision,
+    /// which can't be seen with repeating or mirrored textures.
+    pub fn scroll_uv(&mut self, delta_time: Duration) {
+        let offset = self.uv_offset + self.uv_scroll * delta_time.as_secs_f32();
+        // Mirrored textures only repeat every two widths.
+        self.uv_offset = Vec2::new(offset.x.rem_euclid(2.0), offset.y.rem_euclid(2.0));
     }
 }
 
 enum SpriteType {
     Color,
     Texture(TextureID),
+    /// Drawn with a texture multiplied by the colour of each vertex.
+    ColorTexture(TextureID),
+    /// One layer of a texture array.
+    TextureArray(TextureID, u32),
+    /// Drawn with the text shader from the texture, which is the glyph atlas.
+    Text(TextureID),
+}
+
+/// The vertices and indices of a rect covering the whole texture, shared by every
+/// sprite made with `Sprite::new_texture_rect`.
+pub(crate) fn unit_quad(device: &Device) -> (Arc<Buffer>, Arc<Buffer>) {
+    let vertices = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("unit_quad_vertices"),
+        contents: bytemuck::cast_slice(&[
+            TextureVertex {
+                position: [-0.5, -0.5, 0.0],
+                tex_coords: [0.0, 1.0],
+            },
+            TextureVertex {
+                position: [0.5, -0.5, 0.0],
+                tex_coords: [1.0, 1.0],
+            },
+            TextureVertex {
+                position: [0.5, 0.5, 0.0],
+                tex_coords: [1.0, 0.0],
+            },
+            TextureVertex {
+                position: [-0.5, 0.5, 0.0],
+                tex_coords: [0.0, 0.0],
+            },
+        ]),
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+    let indices = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("unit_quad_indices"),
+        contents: bytemuck::cast_slice::<u16, u8>(&[0, 1, 2, 0, 2, 3]),
+        usage: wgpu::BufferUsages::INDEX,
+    });
+    (Arc::new(vertices), Arc::new(indices))
+}
+
+/// The type of a sprite's vertices.
+pub trait MeshVertex: bytemuck::Pod {
+    /// If the vertex is partially transparent, so the sprite has to be sorted.
+    fn is_translucent(&self) -> bool {
+        false
+    }
+}
+
+impl MeshVertex for ColorVertex {
+    fn is_translucent(&self) -> bool {
+        self.color[3] < 1.0
+    }
+}
+
+impl MeshVertex for TextureVertex {}
+
+impl MeshVertex for ColorTextureVertex {
+    fn is_translucent(&self) -> bool {
+        self.color[3] < 1.0
+    }
+}
+
+impl MeshVertex for TextVertex {}
+
+/// The type of a sprite's indices, either `u16` or `u32`.
+pub trait MeshIndex: bytemuck::Pod {
+    const FORMAT: wgpu::IndexFormat;
+}
+
+impl MeshIndex for u16 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint16;
+}
+
+impl MeshIndex for u32 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32;
+}
+
+/// Make a buffer holding `contents` that can be written to later.
+fn init_buffer(graphics: &Graphics, contents: &[u8], usage: wgpu::BufferUsages) -> Arc<Buffer> {
+    Arc::new(graphics.device.create_buffer_init(&BufferInitDescriptor {
+        label: None,
+        contents,
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+    }))
+}
+
+/// The size of the buffer `init_buffer` makes for `contents`, which is padded to a
+/// multiple of four bytes.
+fn writable_size<T>(contents: &[T]) -> u64 {
+    (std::mem::size_of_val(contents) as u64).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+}
+
+fn make_buffer(graphics: &Graphics, size: u64, usage: wgpu::BufferUsages) -> Buffer {
+    graphics.device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size,
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Write `contents` to the start of `buffer`, replacing it with a bigger one if they
+/// don't fit.
+fn write_buffer(
+    graphics: &Graphics,
+    buffer: &mut Arc<Buffer>,
+    capacity: &mut u64,
+    contents: &[u8],
+    usage: wgpu::BufferUsages,
+) {
+    // Writes have to be a multiple of four bytes, which an odd number of `u16`
+    // indices isn't.
+    let padded;
+    let contents = match contents.len() % 4 {
+        0 => contents,
+        remainder => {
+            padded = [contents, &[0; 4][remainder..]].concat();
+            &padded
+        }
+    };
+
+    let size = contents.len() as u64;
+    if size > *capacity {
+        *capacity = size.next_power_of_two();
+        *buffer = Arc::new(make_buffer(graphics, *capacity, usage));
+    }
+    graphics.queue.write_buffer(buffer, 0, contents);
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -440,47 +971,14 @@ impl Transform {
         self
     }
 
-    fn matrix(&self, frame_size: Vec2) -> [[f32; 4]; 4] {
-        let half = frame_size / 2.0;
-        let projection = Mat4::orthographic_rh(-half.x, half.x, -half.y, half.y, -100.0, 100.0);
-
-        (projection
-            * Mat4::from_scale_rotation_translation(
-                self.scale.extend(1.0),
-                self.rotation,
-                self.translation,
-            ))
+    pub(crate) fn matrix(&self) -> [[f32; 4]; 4] {
+        Mat4::from_scale_rotation_translation(
+            self.scale.extend(1.0),
+            self.rotation,
+            self.translation,
+        )
         .to_cols_array_2d()
     }
-
-    pub(crate) fn desc<'a>() -> VertexBufferLayout<'a> {
-        VertexBufferLayout {
-            array_stride: size_of::<Self>() as u64,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[
-                VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: 0,
-                    shader_location: 2,
-                },
-                VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: size_of::<[f32; 4]>() as u64,
-                    shader_location: 3,
-                },
-                VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: size_of::<[f32; 8]>() as u64,
-                    shader_location: 4,
-                },
-                VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: size_of::<[f32; 12]>() as u64,
-                    shader_location: 5,
-                },
-            ],
-        }
-    }
 }
 
 impl Default for Transform {
@@ -493,11 +991,178 @@ impl Default for Transform {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Filter {
     Linear,
     Nearest,
 }
 
+/// How a texture is sampled when it is drawn, and if it has mipmaps. A `Filter` can be
+/// used anywhere a `SamplerDesc` is expected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerDesc {
+    /// Used when the texture is drawn larger than its size.
+    pub mag_filter: FilterMode,
+    /// Used when the texture is drawn smaller than its size.
+    pub min_filter: FilterMode,
+    /// Used to blend between mipmap levels.
+    pub mipmap_filter: FilterMode,
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    /// The most samples taken for textures seen at a steep angle, from 1 (off) to 16.
+    /// It is rounded down to a power of two, and ignored unless every filter is linear.
+    pub anisotropy: u8,
+    /// Generate mipmaps when the texture is made, which stops it shimmering when it is
+    /// drawn smaller than its size.
+    pub mipmaps: bool,
+}
+
+impl SamplerDesc {
+    pub fn new(filter: Filter) -> Self {
+        filter.into()
+    }
+
+    /// Linear filtering between and within mipmaps, which are generated.
+    pub fn trilinear() -> Self {
+        Self {
+            mipmap_filter: FilterMode::Linear,
+            mipmaps: true,
+            ..Filter::Linear.into()
+        }
+    }
+
+    pub fn with_mag_filter(mut self, mag_filter: FilterMode) -> Self {
+        self.mag_filter = mag_filter;
+        self
+    }
+
+    pub fn with_min_filter(mut self, min_filter: FilterMode) -> Self {
+        self.min_filter = min_filter;
+        self
+    }
+
+    pub fn with_mipmap_filter(mut self, mipmap_filter: FilterMode) -> Self {
+        self.mipmap_filter = mipmap_filter;
+        self
+    }
+
+    /// Set the address mode of both axes.
+    pub fn with_address_mode(mut self, address_mode: AddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: u8) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn with_mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    pub(crate) fn create(&self, device: &Device) -> Sampler {
+        let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|filter| *filter == FilterMode::Linear);
+        device.create_sampler(&SamplerDescriptor {
+            label: None,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: AddressMode::Repeat,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            anisotropy_clamp: if self.anisotropy > 1 && linear {
+                // Only powers of two are allowed, so round down to one.
+                NonZeroU8::new(1 << (7 - self.anisotropy.min(16).leading_zeros()))
+            } else {
+                None
+            },
+            ..Default::default()
+        })
+    }
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Filter::Linear.into()
+    }
+}
+
+impl From<Filter> for SamplerDesc {
+    fn from(filter: Filter) -> Self {
+        let mode = match filter {
+            Filter::Linear => FilterMode::Linear,
+            Filter::Nearest => FilterMode::Nearest,
+        };
+        Self {
+            mag_filter: mode,
+            min_filter: mode,
+            mipmap_filter: FilterMode::Nearest,
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            anisotropy: 1,
+            mipmaps: false,
+        }
+    }
+}
+
+/// How a sprite's colors are combined with the colors already on screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Standard transparency, the sprite is drawn over the top based on its alpha.
+    #[default]
+    Alpha,
+    /// Adds the sprite's color to the screen, useful for glows and particles.
+    Additive,
+    /// Multiplies the screen by the sprite's color, useful for shadows and light maps.
+    Multiply,
+    /// The inverse of multiply, brightens the screen without going over white.
+    Screen,
+    /// Transparency for colors that have already been multiplied by their alpha.
+    Premultiplied,
+}
+
+impl BlendMode {
+    pub(crate) fn blend_state(self) -> BlendState {
+        match self {
+            Self::Alpha => BlendState::ALPHA_BLENDING,
+            Self::Additive => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                    src_factor: BlendFactor::One,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+            },
+            Self::Multiply => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::Dst,
+                    dst_factor: BlendFactor::Zero,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            },
+            Self::Screen => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::One,
+                    dst_factor: BlendFactor::OneMinusSrc,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            },
+            Self::Premultiplied => BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        }
+    }
+}
+
 pub struct Corner {
     pub h: Horizontal,
     pub v: Vertical,
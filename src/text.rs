@@ -0,0 +1,2143 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::ops::Range;
+use std::path::Path;
+
+use fontdue::FontSettings;
+use glam::{UVec2, Vec2};
+use image::{DynamicImage, RgbaImage};
+use indexmap::IndexMap;
+use rustybuzz::Face;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{Color, Device, Queue, VertexAttribute, VertexBufferLayout};
+
+use crate::bitmap_font::BitmapFont;
+use crate::graphics::{FontID, Frame, Graphics, TextureID, TextureManager};
+use crate::layer::LayerID;
+use crate::pipeline::PipelineKind;
+use crate::rect::Rect;
+use crate::render::DrawCommand;
+use crate::sprite::{Filter, Sprite, TextureVertex, Transform};
+
+/// A font that text can be drawn with, loaded with `Graphics::load_font` or one of
+/// the functions for bitmap fonts.
+pub struct Font {
+    kind: FontKind,
+    /// Fonts that are used, in order, for characters that this font doesn't have.
+    pub fallbacks: Vec<FontID>,
+}
+
+enum FontKind {
+    /// A true type or open type font, which is rasterised at every size it is used at.
+    /// The file's contents are kept for shaping, unless they can't be shaped.
+    Vector {
+        font: fontdue::Font,
+        face: Option<ShapingFace>,
+    },
+    /// A font made of images, which are scaled to the size they are used at.
+    Bitmap(BitmapFont),
+}
+
+self_cell::self_cell!(
+    /// The contents of a font file, with the tables used for shaping read from them once
+    /// when the font is loaded.
+    struct ShapingFace {
+        owner: Vec<u8>,
+        #[covariant]
+        dependent: Face,
+    }
+);
+
+/// A glyph made by `Font::shape`.
+pub(crate) struct ShapedGlyph {
+    pub index: u16,
+    /// The byte of the shaped text where the characters the glyph was made from start.
+    pub cluster: usize,
+    pub advance: f32,
+    /// How far the glyph is moved from the pen when it is drawn.
+    pub offset: Vec2,
+}
+
+/// Why a bitmap font couldn't be loaded.
+#[derive(Debug)]
+pub enum FontError {
+    Io(std::io::Error),
+    /// One of the font's images couldn't be loaded.
+    Image(image::ImageError),
+    /// The font's files aren't laid out the way the format requires.
+    Malformed(&'static str),
+}
+
+impl std::fmt::Display for FontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "couldn't read the font: {error}"),
+            Self::Image(error) => write!(f, "couldn't load the font's image: {error}"),
+            Self::Malformed(reason) => write!(f, "the font is malformed: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+impl From<std::io::Error> for FontError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<image::ImageError> for FontError {
+    fn from(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+/// A glyph's image, along with where it goes.
+pub(crate) struct GlyphImage {
+    /// The offset from the glyph's origin to the bottom left of its image.
+    pub offset: Vec2,
+    pub size: UVec2,
+    /// The coverage of every pixel of the glyph, from the top left.
+    pub coverage: Vec<u8>,
+}
+
+impl Font {
+    /// Load a true type or open type font from the contents of its file.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            kind: FontKind::Vector {
+                font: fontdue::Font::from_bytes(bytes, FontSettings::default()).unwrap(),
+                face: ShapingFace::try_new(bytes.to_vec(), |data| {
+                    Face::from_slice(data, 0).ok_or(())
+                })
+                .ok(),
+            },
+            fallbacks: Vec::new(),
+        }
+    }
+
+    /// Load an AngelCode BMFont from its `.fnt` file, in either the text or binary
+    /// format. The pages it uses are loaded from the same directory, and the alpha of
+    /// their pixels is used as the shape of the glyphs.
+    pub fn load_bmfont<T: AsRef<Path>>(path: T) -> Result<Self, FontError> {
+        Ok(Self {
+            kind: FontKind::Bitmap(BitmapFont::load_bmfont(path.as_ref())?),
+            fallbacks: Vec::new(),
+        })
+    }
+
+    /// Make a font from an image split into a grid of `cell_size` pixel cells, which
+    /// hold each of `characters` in order from the top left, row by row.
+    /// The cells can't be empty.
+    pub fn from_grid(
+        image: &DynamicImage,
+        cell_size: UVec2,
+        characters: &str,
+    ) -> Result<Self, FontError> {
+        Ok(Self {
+            kind: FontKind::Bitmap(BitmapFont::from_grid(
+                &image.to_rgba8(),
+                cell_size,
+                characters,
+            )?),
+            fallbacks: Vec::new(),
+        })
+    }
+
+    pub fn with_fallbacks(mut self, fallbacks: Vec<FontID>) -> Self {
+        self.fallbacks = fallbacks;
+        self
+    }
+
+    /// The distance between the baselines of two lines of text, before `line_spacing`
+    /// is applied.
+    pub fn line_height(&self, size: f32) -> f32 {
+        self.line_metrics(size).new_line_size
+    }
+
+    /// The filter the font's glyphs should be drawn with, which keeps bitmap fonts
+    /// crisp.
+    pub(crate) fn filter(&self) -> Filter {
+        match self.kind {
+            FontKind::Vector { .. } => Filter::Linear,
+            FontKind::Bitmap(_) => Filter::Nearest,
+        }
+    }
+
+    fn line_metrics(&self, size: f32) -> fontdue::LineMetrics {
+        let fallback = fontdue::LineMetrics {
+            ascent: size,
+            descent: 0.0,
+            line_gap: 0.0,
+            new_line_size: size,
+        };
+        match &self.kind {
+            FontKind::Vector { font, .. } => font.horizontal_line_metrics(size).unwrap_or(fallback),
+            FontKind::Bitmap(font) => {
+                let scale = size / font.size;
+                fontdue::LineMetrics {
+                    ascent: font.base * scale,
+                    descent: (font.base - font.line_height) * scale,
+                    line_gap: 0.0,
+                    new_line_size: font.line_height * scale,
+                }
+            }
+        }
+    }
+
+    /// The size glyphs are rasterised at when they are drawn at `size`.
+    pub(crate) fn raster_size(&self, size: f32) -> f32 {
+        match &self.kind {
+            // Rounded to quarter pixels, so that animating the size doesn't fill the
+            // glyph atlas with a copy of every glyph at every size.
+            FontKind::Vector { .. } => (size * 4.0).round().max(1.0) / 4.0,
+            FontKind::Bitmap(font) => font.size,
+        }
+    }
+
+    fn glyph_index(&self, character: char) -> u16 {
+        match &self.kind {
+            FontKind::Vector { font, .. } => font.lookup_glyph_index(character),
+            FontKind::Bitmap(font) => font.characters.get(&character).copied().unwrap_or(u16::MAX),
+        }
+    }
+
+    /// If the font has a glyph for `character`, rather than drawing it as a box.
+    fn has_glyph(&self, character: char) -> bool {
+        match &self.kind {
+            FontKind::Vector { font, .. } => font.lookup_glyph_index(character) != 0,
+            FontKind::Bitmap(font) => font.characters.contains_key(&character),
+        }
+    }
+
+    /// Turn `text` into glyphs, in the same order as the text. Vector fonts are shaped,
+    /// so that ligatures, combining marks and scripts that join their letters are drawn
+    /// correctly, while bitmap fonts just have their kerning pairs applied.
+    pub(crate) fn shape(&self, text: &str, size: f32, rtl: bool) -> Vec<ShapedGlyph> {
+        let face = match &self.kind {
+            FontKind::Vector { face, .. } => face.as_ref().map(ShapingFace::borrow_dependent),
+            FontKind::Bitmap(_) => None,
+        };
+        if let Some(face) = face {
+            let mut buffer = rustybuzz::UnicodeBuffer::new();
+            buffer.push_str(text);
+            buffer.guess_segment_properties();
+            buffer.set_direction(match rtl {
+                true => rustybuzz::Direction::RightToLeft,
+                false => rustybuzz::Direction::LeftToRight,
+            });
+            let output = rustybuzz::shape(face, &[], buffer);
+            let scale = size / face.units_per_em() as f32;
+
+            let mut glyphs = output
+                .glyph_infos()
+                .iter()
+                .zip(output.glyph_positions())
+                .map(|(info, position)| ShapedGlyph {
+                    index: info.glyph_id as u16,
+                    cluster: info.cluster as usize,
+                    advance: position.x_advance as f32 * scale,
+                    offset: Vec2::new(position.x_offset as f32, position.y_offset as f32) * scale,
+                })
+                .collect::<Vec<_>>();
+            // Right to left text comes out in the order it is drawn.
+            if rtl {
+                glyphs.reverse();
+            }
+            return glyphs;
+        }
+
+        let mut glyphs: Vec<ShapedGlyph> = Vec::new();
+        for (cluster, character) in text.char_indices() {
+            let index = self.glyph_index(character);
+            if let Some(previous) = glyphs.last_mut() {
+                previous.advance += self.kern(previous.index, index, size).unwrap_or(0.0);
+            }
+            glyphs.push(ShapedGlyph {
+                index,
+                cluster,
+                advance: self.advance(index, size),
+                offset: Vec2::ZERO,
+            });
+        }
+        glyphs
+    }
+
+    fn advance(&self, index: u16, size: f32) -> f32 {
+        match &self.kind {
+            FontKind::Vector { font, .. } => font.metrics_indexed(index, size).advance_width,
+            FontKind::Bitmap(font) => font
+                .glyphs
+                .get(index as usize)
+                .map_or(0.0, |glyph| glyph.advance * size / font.size),
+        }
+    }
+
+    fn kern(&self, left: u16, right: u16, size: f32) -> Option<f32> {
+        match &self.kind {
+            FontKind::Vector { font, .. } => font.horizontal_kern_indexed(left, right, size),
+            FontKind::Bitmap(font) => font
+                .kerning
+                .get(&(left, right))
+                .map(|amount| amount * size / font.size),
+        }
+    }
+
+    /// The size in pixels of a glyph drawn by `rasterize`, found without drawing it.
+    fn glyph_size(&self, index: u16, size: f32) -> UVec2 {
+        match &self.kind {
+            FontKind::Vector { font, .. } => {
+                let metrics = font.metrics_indexed(index, size);
+                UVec2::new(metrics.width as u32, metrics.height as u32)
+            }
+            FontKind::Bitmap(font) => font
+                .glyphs
+                .get(index as usize)
+                .map_or(UVec2::ZERO, |glyph| glyph.size),
+        }
+    }
+
+    /// Draw a glyph at `raster_size(size)`.
+    pub(crate) fn rasterize(&self, index: u16, size: f32) -> GlyphImage {
+        match &self.kind {
+            FontKind::Vector { font, .. } => {
+                let (metrics, coverage) = font.rasterize_indexed(index, size);
+                GlyphImage {
+                    offset: Vec2::new(metrics.xmin as f32, metrics.ymin as f32),
+                    size: UVec2::new(metrics.width as u32, metrics.height as u32),
+                    coverage,
+                }
+            }
+            FontKind::Bitmap(font) => match font.glyphs.get(index as usize) {
+                Some(glyph) => GlyphImage {
+                    offset: glyph.offset,
+                    size: glyph.size,
+                    coverage: glyph.coverage.clone(),
+                },
+                None => GlyphImage {
+                    offset: Vec2::ZERO,
+                    size: UVec2::ZERO,
+                    coverage: Vec::new(),
+                },
+            },
+        }
+    }
+}
+
+/// How the lines of a block of text are lined up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    /// Lines start at the origin of the text.
+    #[default]
+    Left,
+    /// Lines are centred on the origin of the text.
+    Center,
+    /// Lines end at the origin of the text.
+    Right,
+    /// Lines start at the origin of the text, and lines that were wrapped are stretched
+    /// to fill `TextStyle::max_width` by widening their spaces.
+    Justify,
+}
+
+/// Controls the size, color and layout of text.
+#[derive(Clone, Copy, Debug)]
+pub struct TextStyle {
+    /// The height of the font, in frame coordinates.
+    pub size: f32,
+    pub color: Color,
+    /// If this is `Some`, lines are wrapped between words so that they are no wider than
+    /// this. Words that are wider on their own are split.
+    pub max_width: Option<f32>,
+    pub align: Align,
+    /// Multiplies the distance between lines, 1 uses the spacing the font asks for.
+    pub line_spacing: f32,
+    /// If this is `Some`, the glyphs are drawn from signed distance fields, which stay
+    /// sharp when the text is scaled up and can have outlines, shadows and glows.
+    pub sdf: Option<SdfStyle>,
+}
+
+impl TextStyle {
+    pub fn new(size: f32, color: Color) -> Self {
+        Self {
+            size,
+            color,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn with_align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_line_spacing(mut self, line_spacing: f32) -> Self {
+        self.line_spacing = line_spacing;
+        self
+    }
+
+    pub fn with_sdf(mut self, sdf: SdfStyle) -> Self {
+        self.sdf = Some(sdf);
+        self
+    }
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            size: 32.0,
+            color: Color::WHITE,
+            max_width: None,
+            align: Align::Left,
+            line_spacing: 1.0,
+            sdf: None,
+        }
+    }
+}
+
+/// The size glyphs of vector fonts are drawn into distance fields at.
+const SDF_SIZE: f32 = 48.0;
+/// How far the distance fields reach from the edges of glyphs, in atlas pixels.
+const SDF_SPREAD: u32 = 8;
+
+/// The effects drawn around text that uses a signed distance field. Widths and offsets
+/// are in frame coordinates at `TextStyle::size`. Effects reach at most a sixth of the
+/// size of the font out from the glyphs, past which they are cut off.
+#[derive(Clone, Copy, Debug)]
+pub struct SdfStyle {
+    pub outline_width: f32,
+    pub outline_color: Color,
+    /// How far the shadow is moved from the text, with positive Y going up.
+    pub shadow_offset: Vec2,
+    pub shadow_color: Color,
+    /// How far the edge of the shadow is blurred.
+    pub shadow_softness: f32,
+    /// How far the glow fades out from the outline, or the glyphs if there is none.
+    pub glow_width: f32,
+    pub glow_color: Color,
+}
+
+impl SdfStyle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_outline(mut self, width: f32, color: Color) -> Self {
+        self.outline_width = width;
+        self.outline_color = color;
+        self
+    }
+
+    pub fn with_shadow(mut self, offset: Vec2, softness: f32, color: Color) -> Self {
+        self.shadow_offset = offset;
+        self.shadow_softness = softness;
+        self.shadow_color = color;
+        self
+    }
+
+    pub fn with_glow(mut self, width: f32, color: Color) -> Self {
+        self.glow_width = width;
+        self.glow_color = color;
+        self
+    }
+
+    /// Convert the style to the units of the distance field, where `scale` is the
+    /// number of atlas pixels in one frame unit.
+    fn uniform(&self, scale: f32) -> SdfUniform {
+        let distance = |width: f32| width * scale / (2 * SDF_SPREAD) as f32;
+        SdfUniform {
+            outline_color: color_array(self.outline_color),
+            shadow_color: color_array(self.shadow_color),
+            glow_color: color_array(self.glow_color),
+            shadow_offset: (Vec2::new(self.shadow_offset.x, -self.shadow_offset.y) * scale).into(),
+            outline_width: distance(self.outline_width),
+            glow_width: distance(self.glow_width),
+            shadow_softness: distance(self.shadow_softness),
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+impl Default for SdfStyle {
+    fn default() -> Self {
+        Self {
+            outline_width: 0.0,
+            outline_color: Color::TRANSPARENT,
+            shadow_offset: Vec2::ZERO,
+            shadow_color: Color::TRANSPARENT,
+            shadow_softness: 0.0,
+            glow_width: 0.0,
+            glow_color: Color::TRANSPARENT,
+        }
+    }
+}
+
+/// The layout of `SdfStyle` in the uniform read by the SDF text shader.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SdfUniform {
+    outline_color: [f32; 4],
+    shadow_color: [f32; 4],
+    glow_color: [f32; 4],
+    shadow_offset: [f32; 2],
+    outline_width: f32,
+    glow_width: f32,
+    shadow_softness: f32,
+    _padding: [f32; 3],
+}
+
+/// The buffer holding the `SdfStyle` of a block of text.
+struct SdfBinding {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// The fonts and icons that rich text markup can refer to. Markup is made of tags in
+/// square brackets:
+///
+/// - `[color=red]...[/color]` changes the color, by name or as `#rrggbb` or `#rrggbbaa`.
+/// - `[size=24]...[/size]` changes the size of the font, which must be above 0.
+/// - `[b]...[/b]` and `[i]...[/i]` switch to the bold and italic fonts.
+/// - `[font=name]...[/font]` switches to a font in `fonts`, or a `FontID` in
+///   `Graphics::fonts` if the name is a number. Fonts that aren't loaded are ignored.
+/// - `[img=name]` places an icon from `icons`, as a square the size of the font.
+/// - `[[` is a literal `[`. Tags that aren't understood are drawn as they are.
+#[derive(Clone, Debug, Default)]
+pub struct Markup {
+    pub bold: Option<FontID>,
+    pub italic: Option<FontID>,
+    /// Used when text is both bold and italic, falling back to `bold` if it is `None`.
+    pub bold_italic: Option<FontID>,
+    pub fonts: HashMap<String, FontID>,
+    pub icons: HashMap<String, TextureID>,
+}
+
+impl Markup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_bold(mut self, font: FontID) -> Self {
+        self.bold = Some(font);
+        self
+    }
+
+    pub fn with_italic(mut self, font: FontID) -> Self {
+        self.italic = Some(font);
+        self
+    }
+
+    pub fn with_bold_italic(mut self, font: FontID) -> Self {
+        self.bold_italic = Some(font);
+        self
+    }
+
+    pub fn with_font(mut self, name: &str, font: FontID) -> Self {
+        self.fonts.insert(name.to_owned(), font);
+        self
+    }
+
+    pub fn with_icon(mut self, name: &str, texture: TextureID) -> Self {
+        self.icons.insert(name.to_owned(), texture);
+        self
+    }
+
+    /// Split `markup` into the characters and icons it draws.
+    fn parse(
+        &self,
+        markup: &str,
+        font: FontID,
+        style: &TextStyle,
+        fonts: &IndexMap<FontID, Font>,
+    ) -> Vec<TextItem> {
+        let mut state = MarkupState {
+            colors: vec![color_array(style.color)],
+            sizes: vec![style.size],
+            fonts: vec![font],
+            bold: 0,
+            italic: 0,
+        };
+        let mut items = Vec::new();
+
+        let mut rest = markup;
+        while let Some(character) = rest.chars().next() {
+            if let Some(after) = rest.strip_prefix("[[") {
+                items.push(state.item(self, '[', None));
+                rest = after;
+                continue;
+            }
+            if let Some(end) = rest.find(']').filter(|_| character == '[') {
+                if state.apply(self, fonts, &rest[1..end], &mut items) {
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+
+            items.push(state.item(self, character, None));
+            rest = &rest[character.len_utf8()..];
+        }
+
+        items
+    }
+}
+
+/// The styles that are applied at a point in some markup, where the last of each list
+/// is the current one.
+struct MarkupState {
+    colors: Vec<[f32; 4]>,
+    sizes: Vec<f32>,
+    fonts: Vec<FontID>,
+    bold: u32,
+    italic: u32,
+}
+
+impl MarkupState {
+    fn item(&self, markup: &Markup, character: char, icon: Option<TextureID>) -> TextItem {
+        let font = *self.fonts.last().unwrap();
+        TextItem {
+            character,
+            font: match (self.bold > 0, self.italic > 0) {
+                (true, true) => markup.bold_italic.or(markup.bold).unwrap_or(font),
+                (true, false) => markup.bold.unwrap_or(font),
+                (false, true) => markup.italic.unwrap_or(font),
+                (false, false) => font,
+            },
+            size: *self.sizes.last().unwrap(),
+            color: *self.colors.last().unwrap(),
+            icon,
+        }
+    }
+
+    /// Apply the tag between the square brackets, returning false if it isn't
+    /// understood.
+    fn apply(
+        &mut self,
+        markup: &Markup,
+        fonts: &IndexMap<FontID, Font>,
+        tag: &str,
+        items: &mut Vec<TextItem>,
+    ) -> bool {
+        let (name, value) = tag.split_once('=').unwrap_or((tag, ""));
+        match name {
+            "color" => match parse_color(value) {
+                Some(color) => self.colors.push(color),
+                None => return false,
+            },
+            "size" => match value.parse::<f32>() {
+                Ok(size) if size.is_finite() && size > 0.0 => self.sizes.push(size),
+                _ => return false,
+            },
+            "font" => match markup.fonts.get(value).copied().or(value.parse().ok()) {
+                Some(font) if fonts.contains_key(&font) => self.fonts.push(font),
+                _ => return false,
+            },
+            "img" => match markup.icons.get(value) {
+                Some(&icon) => items.push(self.item(markup, '\u{fffc}', Some(icon))),
+                None => return false,
+            },
+            "b" => self.bold += 1,
+            "i" => self.italic += 1,
+            "/color" if self.colors.len() > 1 => drop(self.colors.pop()),
+            "/size" if self.sizes.len() > 1 => drop(self.sizes.pop()),
+            "/font" if self.fonts.len() > 1 => drop(self.fonts.pop()),
+            "/b" if self.bold > 0 => self.bold -= 1,
+            "/i" if self.italic > 0 => self.italic -= 1,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// A block of text whose glyphs are drawn from a texture shared by all text, so
+/// changing the string only rebuilds its mesh. The origin of the text is the top of its
+/// first line, horizontally placed depending on `TextStyle::align`.
+///
+/// When the glyph atlas fills up it is cleared, and text built before then draws the
+/// wrong glyphs until it is rebuilt. Call `refresh` on text that is kept between frames
+/// before drawing it, which only rebuilds it when that has happened.
+pub struct Text {
+    /// The sprite the text is drawn with, which holds its transform and layer.
+    pub sprite: Sprite,
+    font: FontID,
+    text: String,
+    style: TextStyle,
+    /// If this is `Some`, the text is parsed as markup.
+    markup: Option<Markup>,
+    size: Vec2,
+    /// The number of characters that are drawn, or all of them if this is `None`.
+    visible: Option<usize>,
+    character_count: usize,
+    /// The character each quad of the mesh belongs to, in order.
+    quads: Vec<usize>,
+    icons: Vec<Icons>,
+    /// The glyph atlas texture, which is filtered the way the font wants.
+    atlas: TextureID,
+    /// The style of the distance field, if the text uses one.
+    sdf: Option<SdfBinding>,
+    /// The generation of the atlas when the text was built.
+    generation: u32,
+}
+
+/// The icons in a block of text that use the same texture, which are drawn together.
+struct Icons {
+    texture: TextureID,
+    sprite: Sprite,
+    quads: Vec<usize>,
+}
+
+impl Text {
+    pub fn new(graphics: &mut Graphics, font: FontID, text: &str, style: TextStyle) -> Self {
+        Self::new_inner(graphics, font, text, style, None)
+    }
+
+    /// Make text from `markup`, where the tags it can use are described in `Markup`.
+    pub fn new_markup(
+        graphics: &mut Graphics,
+        font: FontID,
+        markup: &str,
+        style: TextStyle,
+        context: Markup,
+    ) -> Self {
+        Self::new_inner(graphics, font, markup, style, Some(context))
+    }
+
+    fn new_inner(
+        graphics: &mut Graphics,
+        font: FontID,
+        text: &str,
+        style: TextStyle,
+        markup: Option<Markup>,
+    ) -> Self {
+        let mut text = Self {
+            sprite: Sprite::new_text_mesh(graphics, &[], &[]),
+            font,
+            text: text.to_owned(),
+            style,
+            markup,
+            size: Vec2::ZERO,
+            visible: None,
+            character_count: 0,
+            quads: Vec::new(),
+            icons: Vec::new(),
+            atlas: graphics.glyph_atlas.id,
+            sdf: None,
+            generation: 0,
+        };
+        text.rebuild(graphics);
+        text
+    }
+
+    /// The string the text was made from, including any markup.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn style(&self) -> &TextStyle {
+        &self.style
+    }
+
+    /// The width and height of the laid out text.
+    pub fn size(&self) -> Vec2 {
+        self.size
+    }
+
+    /// The number of characters and icons in the text, not counting markup tags.
+    pub fn character_count(&self) -> usize {
+        self.character_count
+    }
+
+    /// Change the string that is drawn, which is parsed as markup if the text was made
+    /// with `new_markup`. Nothing is rebuilt if it hasn't changed.
+    pub fn set_text(&mut self, graphics: &mut Graphics, text: &str) {
+        if self.text != text {
+            self.text = text.to_owned();
+            self.rebuild(graphics);
+        }
+    }
+
+    pub fn set_style(&mut self, graphics: &mut Graphics, style: TextStyle) {
+        self.style = style;
+        self.rebuild(graphics);
+    }
+
+    pub fn set_font(&mut self, graphics: &mut Graphics, font: FontID) {
+        self.font = font;
+        self.rebuild(graphics);
+    }
+
+    /// Rebuild the text if the glyph atlas it uses has been cleared since it was built,
+    /// which happens when the atlas can't grow any more or the fonts are cleared.
+    /// Calling this every frame keeps text that has been built for a while correct.
+    pub fn refresh(&mut self, graphics: &mut Graphics) {
+        if self.generation != self.atlas_of(graphics).generation() {
+            self.rebuild(graphics);
+        }
+    }
+
+    fn atlas_of<'a>(&self, graphics: &'a Graphics) -> &'a GlyphAtlas {
+        match self.style.sdf {
+            Some(_) => &graphics.sdf_atlas,
+            None => &graphics.glyph_atlas,
+        }
+    }
+
+    /// Only draw the first `count` characters and icons, or all of them if it is `None`.
+    /// The text is laid out as if it were all visible, so that words don't jump between
+    /// lines as they are revealed by a typewriter effect.
+    pub fn set_visible_characters(&mut self, count: Option<usize>) {
+        self.visible = count;
+    }
+
+    pub fn with_visible_characters(mut self, count: Option<usize>) -> Self {
+        self.visible = count;
+        self
+    }
+
+    fn rebuild(&mut self, graphics: &mut Graphics) {
+        let items = match &self.markup {
+            Some(markup) => markup.parse(&self.text, self.font, &self.style, &graphics.fonts),
+            None => plain_items(&self.text, self.font, &self.style),
+        };
+        // If the atlas fills up and is cleared part way through, the glyphs before that
+        // are gone, so the mesh is built again into the emptied atlas.
+        let generation = self.atlas_of(graphics).generation();
+        let mut mesh = build_mesh(graphics, &items, &self.style);
+        if self.atlas_of(graphics).generation() != generation {
+            mesh = build_mesh(graphics, &items, &self.style);
+        }
+        self.generation = self.atlas_of(graphics).generation();
+
+        self.sprite
+            .update_mesh(graphics, &mesh.vertices, &mesh.indices);
+        self.quads = mesh.quads;
+        self.size = mesh.size;
+        self.atlas = match self.style.sdf {
+            Some(_) => graphics.sdf_atlas.id,
+            None => graphics
+                .glyph_atlas
+                .texture_id(graphics.fonts[&self.font].filter()),
+        };
+        self.update_sdf(graphics);
+        self.character_count = items.len();
+
+        let mut old_icons = std::mem::take(&mut self.icons);
+        for (texture, icon_mesh) in mesh.icons {
+            let sprite = match old_icons.iter().position(|icons| icons.texture == texture) {
+                Some(i) => {
+                    let mut sprite = old_icons.swap_remove(i).sprite;
+                    sprite.update_mesh(graphics, &icon_mesh.vertices, &icon_mesh.indices);
+                    sprite
+                }
+                None => Sprite::new_texture_mesh(
+                    graphics,
+                    &icon_mesh.vertices,
+                    &icon_mesh.indices,
+                    texture,
+                ),
+            };
+            self.icons.push(Icons {
+                texture,
+                sprite,
+                quads: icon_mesh.quads,
+            });
+        }
+    }
+
+    fn update_sdf(&mut self, graphics: &Graphics) {
+        let style = match self.style.sdf {
+            Some(style) => style,
+            None => {
+                self.sdf = None;
+                return;
+            }
+        };
+        let scale = graphics.fonts[&self.font].raster_size(SDF_SIZE) / self.style.size;
+        let uniform = style.uniform(scale);
+
+        match &self.sdf {
+            Some(sdf) => graphics
+                .queue
+                .write_buffer(&sdf.buffer, 0, bytemuck::bytes_of(&uniform)),
+            None => {
+                let buffer = graphics.device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("sdf_style_buffer"),
+                    contents: bytemuck::bytes_of(&uniform),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+                let bind_group = graphics
+                    .device
+                    .create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("sdf_style_bind_group"),
+                        layout: &graphics.renderer.pipelines.text_style_layout,
+                        entries: &[wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: buffer.as_entire_binding(),
+                        }],
+                    });
+                self.sdf = Some(SdfBinding { buffer, bind_group });
+            }
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.sprite.transform = transform;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: LayerID) -> Self {
+        self.sprite.layer = layer;
+        self
+    }
+
+    /// Queue the text to be drawn once the frame is finished. Call `refresh` first if
+    /// the text was built before this frame, as its glyphs may have been cleared.
+    pub fn render_to<'a>(&'a self, frame: &mut Frame<'a>) {
+        let instance = self.sprite.instance();
+        let base = self.sprite.draw_command(frame.texture_manager);
+        let visible_indices = |quads: &[usize]| match self.visible {
+            Some(visible) => quads.partition_point(|&item| item < visible) as u32 * 6,
+            None => quads.len() as u32 * 6,
+        };
+
+        frame.push(
+            DrawCommand {
+                kind: match self.sdf {
+                    Some(_) => PipelineKind::SdfText,
+                    None => base.kind,
+                },
+                index_count: visible_indices(&self.quads),
+                bind_group: Some(&frame.texture_manager[self.atlas]),
+                uniforms: self.sdf.as_ref().map(|sdf| &sdf.bind_group),
+                ..base
+            },
+            instance,
+        );
+        for icons in &self.icons {
+            let command = icons.sprite.draw_command(frame.texture_manager);
+            frame.push(
+                DrawCommand {
+                    kind: command.kind,
+                    vertex_buffer: command.vertex_buffer,
+                    index_buffer: command.index_buffer,
+                    index_count: visible_indices(&icons.quads),
+                    index_format: command.index_format,
+                    bind_group: command.bind_group,
+                    uniforms: None,
+                    ..base
+                },
+                instance,
+            );
+        }
+    }
+}
+
+/// The layout of a block of text, found by `Graphics::measure_text` without drawing
+/// it. Positions use the same space as `Text`, where the origin is the top left of the
+/// first line and Y goes up.
+#[derive(Clone, Debug)]
+pub struct TextMetrics {
+    /// The width of the widest line and the height of all of them.
+    pub size: Vec2,
+    pub lines: Vec<TextLine>,
+    /// The area of every character apart from newlines, in the order of the text.
+    pub glyphs: Vec<GlyphRect>,
+}
+
+/// A line of measured text.
+#[derive(Clone, Debug)]
+pub struct TextLine {
+    /// The bytes of the text on the line, not including the newline that ends it.
+    pub range: Range<usize>,
+    /// The area of the line, not counting spaces at the end of it.
+    pub rect: Rect,
+    /// The height of the line's baseline.
+    pub baseline: f32,
+}
+
+/// The area taken up by a character of measured text, which is as tall as its line.
+/// Characters drawn as one glyph, such as ligatures, share its width equally.
+#[derive(Clone, Debug)]
+pub struct GlyphRect {
+    /// The bytes of the character in the text.
+    pub range: Range<usize>,
+    /// The index of the line the character is on.
+    pub line: usize,
+    pub rect: Rect,
+    /// If the character is part of right to left text, so that it starts on its right.
+    pub rtl: bool,
+}
+
+impl TextMetrics {
+    /// Where the caret goes when it is before the character starting at `byte`, or at
+    /// the end of the text if `byte` is its length. The caret is a rectangle with no
+    /// width that is as tall as its line.
+    pub fn caret_rect(&self, byte: usize) -> Rect {
+        let line_index = self
+            .lines
+            .iter()
+            .rposition(|line| line.range.start <= byte)
+            .unwrap_or(0);
+        let line = match self.lines.get(line_index) {
+            Some(line) => line,
+            None => return Rect::new(Vec2::ZERO, Vec2::ZERO),
+        };
+        let glyphs = self.glyphs.iter().filter(|glyph| glyph.line == line_index);
+
+        // The caret goes on the leading side of the character after it, or the trailing
+        // side of the character before it at the end of a line.
+        let mut x = line.rect.min.x;
+        for glyph in glyphs {
+            let (leading, trailing) = match glyph.rtl {
+                true => (glyph.rect.max.x, glyph.rect.min.x),
+                false => (glyph.rect.min.x, glyph.rect.max.x),
+            };
+            if glyph.range.start == byte {
+                x = leading;
+                break;
+            } else if glyph.range.start < byte {
+                x = trailing;
+            }
+        }
+
+        Rect::new(Vec2::new(x, line.rect.min.y), Vec2::new(x, line.rect.max.y))
+    }
+
+    /// Find the byte the caret should be placed before when `point` is clicked, which
+    /// is on the nearest line to it and on the nearest side of the nearest character.
+    pub fn hit_test(&self, point: Vec2) -> usize {
+        let line_index = self
+            .lines
+            .iter()
+            .position(|line| point.y >= line.rect.min.y)
+            .unwrap_or(self.lines.len().saturating_sub(1));
+        let line = match self.lines.get(line_index) {
+            Some(line) => line,
+            None => return 0,
+        };
+
+        let distance = |glyph: &&GlyphRect| {
+            (glyph.rect.min.x - point.x)
+                .max(point.x - glyph.rect.max.x)
+                .max(0.0)
+        };
+        let nearest = self
+            .glyphs
+            .iter()
+            .filter(|glyph| glyph.line == line_index)
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)));
+        match nearest {
+            Some(glyph) if (point.x < glyph.rect.center().x) != glyph.rtl => glyph.range.start,
+            Some(glyph) => glyph.range.end,
+            None => line.range.start,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct TextVertex {
+    pub position: [f32; 3],
+    /// The position in the glyph atlas, in pixels.
+    pub tex_coords: [f32; 2],
+    pub color: [f32; 4],
+    /// The corners of the glyph's cell in the glyph atlas, in pixels, so that effects
+    /// sampled away from the glyph don't pick up its neighbours.
+    pub rect: [f32; 4],
+}
+
+impl TextVertex {
+    pub(crate) fn desc<'a>() -> VertexBufferLayout<'a> {
+        VertexBufferLayout {
+            array_stride: size_of::<Self>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: size_of::<[f32; 3]>() as u64,
+                    shader_location: 1,
+                },
+                // The locations in between are used by the instance's transform.
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: size_of::<[f32; 5]>() as u64,
+                    shader_location: 6,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: size_of::<[f32; 9]>() as u64,
+                    shader_location: 10,
+                },
+            ],
+        }
+    }
+}
+
+/// A character or icon to be laid out, along with how it is drawn.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TextItem {
+    pub character: char,
+    pub font: FontID,
+    pub size: f32,
+    pub color: [f32; 4],
+    /// If this is `Some`, the item is drawn as this texture instead of a character.
+    pub icon: Option<TextureID>,
+}
+
+fn plain_items(text: &str, font: FontID, style: &TextStyle) -> Vec<TextItem> {
+    text.chars()
+        .map(|character| TextItem {
+            character,
+            font,
+            size: style.size,
+            color: color_array(style.color),
+            icon: None,
+        })
+        .collect()
+}
+
+fn color_array(color: Color) -> [f32; 4] {
+    [
+        color.r as f32,
+        color.g as f32,
+        color.b as f32,
+        color.a as f32,
+    ]
+}
+
+/// Read a color written as a name, `#rrggbb` or `#rrggbbaa`.
+fn parse_color(value: &str) -> Option<[f32; 4]> {
+    if let Some(hex) = value.strip_prefix('#') {
+        let channel = |i: usize| {
+            hex.get(i * 2..i * 2 + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+                .map(|channel| channel as f32 / 255.0)
+        };
+        return match hex.len() {
+            6 => Some([channel(0)?, channel(1)?, channel(2)?, 1.0]),
+            8 => Some([channel(0)?, channel(1)?, channel(2)?, channel(3)?]),
+            _ => None,
+        };
+    }
+
+    Some(match value {
+        "white" => [1.0, 1.0, 1.0, 1.0],
+        "black" => [0.0, 0.0, 0.0, 1.0],
+        "gray" | "grey" => [0.5, 0.5, 0.5, 1.0],
+        "red" => [1.0, 0.0, 0.0, 1.0],
+        "green" => [0.0, 1.0, 0.0, 1.0],
+        "blue" => [0.0, 0.0, 1.0, 1.0],
+        "yellow" => [1.0, 1.0, 0.0, 1.0],
+        "cyan" => [0.0, 1.0, 1.0, 1.0],
+        "magenta" => [1.0, 0.0, 1.0, 1.0],
+        "orange" => [1.0, 0.5, 0.0, 1.0],
+        _ => return None,
+    })
+}
+
+/// A glyph placed by `layout`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LayoutGlyph {
+    /// The index of the first item the glyph was made from.
+    pub item: usize,
+    /// The font the glyph is drawn with, which is a fallback of the item's font if it
+    /// doesn't have the character.
+    pub font: FontID,
+    pub index: u16,
+    /// The position of the pen on the baseline when the glyph is drawn.
+    pub position: Vec2,
+    /// How far the glyph is moved from the pen when it is drawn.
+    pub offset: Vec2,
+    pub advance: f32,
+    pub space: bool,
+    /// The bidirectional embedding level, where odd levels are right to left.
+    pub level: u8,
+}
+
+/// A line of text placed by `layout`.
+pub(crate) struct LayoutLine {
+    /// The glyphs in the order they are drawn.
+    pub glyphs: Vec<LayoutGlyph>,
+    /// The items on the line, not counting the newline that ends it.
+    pub items: Range<usize>,
+    /// The height of the top of the line, which is zero or below.
+    pub top: f32,
+    pub height: f32,
+    pub baseline: f32,
+    /// The width of the line, not counting spaces at the end of it.
+    pub width: f32,
+}
+
+/// The position of every glyph in a block of text.
+pub(crate) struct Layout {
+    pub lines: Vec<LayoutLine>,
+    pub size: Vec2,
+}
+
+/// Split `items` into lines and place every glyph in them. Text is shaped in runs that
+/// share a font, size and direction, then wrapped in the order it was written before
+/// each line is reordered into the order it is drawn. Newlines aren't given a glyph.
+pub(crate) fn layout(
+    fonts: &IndexMap<FontID, Font>,
+    items: &[TextItem],
+    style: &TextStyle,
+) -> Layout {
+    let item_fonts = resolve_fonts(fonts, items);
+    let levels = bidi_levels(items);
+
+    // Lines as the glyphs in them, whether they were wrapped and the items on them.
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for end in (0..=items.len()).filter(|&i| i == items.len() || is_newline(&items[i])) {
+        let glyphs = shape_items(fonts, items, &item_fonts, &levels, start..end);
+        let mut line_start = start;
+        start = end + 1;
+
+        // Lines can be wrapped after each run of spaces.
+        let mut line: Vec<LayoutGlyph> = Vec::new();
+        let mut width = 0.0;
+        let mut wrap_at = None;
+        for glyph in glyphs {
+            if let Some(max_width) = style.max_width {
+                if !glyph.space && !line.is_empty() && width + glyph.advance > max_width {
+                    let rest = line.split_off(wrap_at.unwrap_or(line.len()));
+                    let next_start = rest.first().map_or(glyph.item, |glyph| glyph.item);
+                    lines.push((line, true, line_start..next_start));
+                    line_start = next_start;
+                    line = rest;
+                    width = line.iter().map(|glyph| glyph.advance).sum();
+                    wrap_at = None;
+                }
+            }
+
+            width += glyph.advance;
+            line.push(glyph);
+            if glyph.space {
+                wrap_at = Some(line.len());
+            }
+        }
+        lines.push((line, false, line_start..end));
+    }
+
+    // Every line is as tall as the biggest font on it, or the default font if it is
+    // empty.
+    let line_metrics = |glyphs: &[LayoutGlyph]| {
+        let default = items
+            .first()
+            .map(|item| fonts[&item.font].line_metrics(item.size))
+            .unwrap_or(fontdue::LineMetrics {
+                ascent: style.size,
+                descent: 0.0,
+                line_gap: 0.0,
+                new_line_size: style.size,
+            });
+        glyphs
+            .iter()
+            .map(|glyph| fonts[&glyph.font].line_metrics(items[glyph.item].size))
+            .reduce(|a, b| fontdue::LineMetrics {
+                ascent: a.ascent.max(b.ascent),
+                descent: a.descent.min(b.descent),
+                line_gap: a.line_gap.max(b.line_gap),
+                new_line_size: a.new_line_size.max(b.new_line_size),
+            })
+            .unwrap_or(default)
+    };
+
+    let mut size = Vec2::ZERO;
+    let lines = lines
+        .into_iter()
+        .map(|(glyphs, wrapped, items)| {
+            let mut glyphs = reorder_line(glyphs);
+            let metrics = line_metrics(&glyphs);
+            let top = -size.y;
+            let baseline = top - metrics.ascent;
+            let mut width = line_width(&glyphs);
+
+            let offset = match style.align {
+                Align::Left | Align::Justify => 0.0,
+                Align::Center => -width / 2.0,
+                Align::Right => -width,
+            };
+            if let (Align::Justify, Some(max_width), true) = (style.align, style.max_width, wrapped)
+            {
+                let visible = glyphs.iter().rposition(|glyph| !glyph.space).unwrap_or(0);
+                let gaps = glyphs[..visible].iter().filter(|glyph| glyph.space).count();
+                if gaps > 0 {
+                    let extra = (max_width - width) / gaps as f32;
+                    let mut shift = 0.0;
+                    for glyph in &mut glyphs {
+                        glyph.position.x += shift;
+                        if glyph.space {
+                            shift += extra;
+                        }
+                    }
+                    width = max_width;
+                }
+            }
+
+            for glyph in &mut glyphs {
+                glyph.position += Vec2::new(offset, baseline);
+            }
+            let height = metrics.new_line_size * style.line_spacing;
+            size.x = size.x.max(width);
+            size.y += height;
+            LayoutLine {
+                glyphs,
+                items,
+                top,
+                height,
+                baseline,
+                width,
+            }
+        })
+        .collect();
+
+    Layout { lines, size }
+}
+
+fn is_newline(item: &TextItem) -> bool {
+    item.character == '\n' && item.icon.is_none()
+}
+
+/// Choose the font every item is drawn with, which is the first of its font and that
+/// font's fallbacks that has a glyph for it. Combining marks stay with the font of the
+/// character before them when they can, so that they are shaped together.
+fn resolve_fonts(fonts: &IndexMap<FontID, Font>, items: &[TextItem]) -> Vec<FontID> {
+    let mut resolved: Vec<FontID> = Vec::with_capacity(items.len());
+    for (i, item) in items.iter().enumerate() {
+        let has_glyph = |font: &FontID| {
+            fonts
+                .get(font)
+                .is_some_and(|font| font.has_glyph(item.character))
+        };
+        let previous = match i {
+            0 => None,
+            _ if items[i - 1].font != item.font => None,
+            _ => resolved.last().copied(),
+        };
+
+        let font = match previous {
+            Some(previous)
+                if unicode_bidi::bidi_class(item.character) == unicode_bidi::BidiClass::NSM
+                    && has_glyph(&previous) =>
+            {
+                previous
+            }
+            _ if item.icon.is_some() || has_glyph(&item.font) => item.font,
+            _ => fonts[&item.font]
+                .fallbacks
+                .iter()
+                .copied()
+                .find(has_glyph)
+                .unwrap_or(item.font),
+        };
+        resolved.push(font);
+    }
+    resolved
+}
+
+/// Find the bidirectional embedding level of every item, with the direction of each
+/// paragraph taken from its first strongly directional character.
+fn bidi_levels(items: &[TextItem]) -> Vec<u8> {
+    // Icons are treated like any other object in the text.
+    let text = items
+        .iter()
+        .map(|item| match item.icon {
+            Some(_) => '\u{fffc}',
+            None => item.character,
+        })
+        .collect::<String>();
+    let info = unicode_bidi::BidiInfo::new(&text, None);
+    text.char_indices()
+        .map(|(byte, _)| info.levels[byte].number())
+        .collect()
+}
+
+/// Shape a paragraph of items in runs that share a font, size and direction, giving
+/// glyphs in the order they were written.
+fn shape_items(
+    fonts: &IndexMap<FontID, Font>,
+    items: &[TextItem],
+    item_fonts: &[FontID],
+    levels: &[u8],
+    range: Range<usize>,
+) -> Vec<LayoutGlyph> {
+    let mut glyphs = Vec::new();
+    let mut start = range.start;
+    while start < range.end {
+        let first = &items[start];
+        let end = (start + 1..range.end)
+            .find(|&i| {
+                first.icon.is_some()
+                    || items[i].icon.is_some()
+                    || item_fonts[i] != item_fonts[start]
+                    || items[i].size != first.size
+                    || levels[i] != levels[start]
+            })
+            .unwrap_or(range.end);
+        let glyph = |item, index, advance, offset| LayoutGlyph {
+            item,
+            font: item_fonts[start],
+            index,
+            position: Vec2::ZERO,
+            offset,
+            advance,
+            space: first.icon.is_none() && items[item].character.is_whitespace(),
+            level: levels[start],
+        };
+
+        if first.icon.is_some() {
+            glyphs.push(glyph(start, 0, first.size, Vec2::ZERO));
+        } else {
+            let mut text = String::new();
+            let mut bytes = Vec::with_capacity(end - start);
+            for item in &items[start..end] {
+                bytes.push(text.len());
+                text.push(item.character);
+            }
+
+            let shaped = fonts[&item_fonts[start]].shape(&text, first.size, levels[start] % 2 == 1);
+            for shaped in shaped {
+                let item = start + bytes.partition_point(|&byte| byte <= shaped.cluster) - 1;
+                glyphs.push(glyph(item, shaped.index, shaped.advance, shaped.offset));
+            }
+        }
+        start = end;
+    }
+    glyphs
+}
+
+/// Put the glyphs of a line in the order they are drawn, reversing every run of right
+/// to left text, and place them along it.
+fn reorder_line(glyphs: Vec<LayoutGlyph>) -> Vec<LayoutGlyph> {
+    let mut order = (0..glyphs.len()).collect::<Vec<_>>();
+    let highest = glyphs.iter().map(|glyph| glyph.level).max().unwrap_or(0);
+    let lowest_odd = glyphs
+        .iter()
+        .map(|glyph| glyph.level | 1)
+        .min()
+        .unwrap_or(1);
+
+    // From the highest level down, reverse every run at that level or higher.
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if glyphs[order[i]].level < level {
+                i += 1;
+                continue;
+            }
+            let end = (i..order.len())
+                .find(|&j| glyphs[order[j]].level < level)
+                .unwrap_or(order.len());
+            order[i..end].reverse();
+            i = end;
+        }
+    }
+
+    let mut pen = 0.0;
+    order
+        .into_iter()
+        .map(|i| {
+            let glyph = LayoutGlyph {
+                position: Vec2::new(pen, 0.0),
+                ..glyphs[i]
+            };
+            pen += glyph.advance;
+            glyph
+        })
+        .collect()
+}
+
+/// The width of a line, not counting spaces at the end of it.
+fn line_width(glyphs: &[LayoutGlyph]) -> f32 {
+    glyphs
+        .iter()
+        .filter(|glyph| !glyph.space)
+        .map(|glyph| glyph.position.x + glyph.advance)
+        .fold(0.0, f32::max)
+}
+
+/// The meshes that draw a block of text, along with the item each of their quads
+/// belongs to.
+struct TextMesh {
+    vertices: Vec<TextVertex>,
+    /// 32 bit, so that text can have more than 16384 glyphs.
+    indices: Vec<u32>,
+    quads: Vec<usize>,
+    /// The meshes of the icons for each texture.
+    icons: IndexMap<TextureID, IconMesh>,
+    size: Vec2,
+}
+
+#[derive(Default)]
+struct IconMesh {
+    vertices: Vec<TextureVertex>,
+    indices: Vec<u32>,
+    quads: Vec<usize>,
+}
+
+fn build_mesh(graphics: &mut Graphics, items: &[TextItem], style: &TextStyle) -> TextMesh {
+    let layout = layout(&graphics.fonts, items, style);
+
+    let mut mesh = TextMesh {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+        quads: Vec::new(),
+        icons: IndexMap::new(),
+        size: layout.size,
+    };
+    // Quads are kept in the order of the text, so that they can be revealed in order
+    // even when right to left text is reordered.
+    let mut glyphs = layout
+        .lines
+        .iter()
+        .flat_map(|line| &line.glyphs)
+        .collect::<Vec<_>>();
+    glyphs.sort_by_key(|glyph| glyph.item);
+    for glyph in glyphs {
+        let item = &items[glyph.item];
+
+        if let Some(icon) = item.icon {
+            let descent = graphics.fonts[&item.font].line_metrics(item.size).descent;
+            let min = glyph.position + Vec2::new(0.0, descent);
+            let max = min + item.size;
+            let icon_mesh = mesh.icons.entry(icon).or_default();
+            let start = icon_mesh.vertices.len() as u32;
+            icon_mesh.vertices.extend(
+                [
+                    (Vec2::new(min.x, min.y), Vec2::new(0.0, 1.0)),
+                    (Vec2::new(max.x, min.y), Vec2::new(1.0, 1.0)),
+                    (Vec2::new(max.x, max.y), Vec2::new(1.0, 0.0)),
+                    (Vec2::new(min.x, max.y), Vec2::new(0.0, 0.0)),
+                ]
+                .map(|(position, tex_coords)| TextureVertex {
+                    position: position.extend(0.0).into(),
+                    tex_coords: tex_coords.into(),
+                }),
+            );
+            icon_mesh
+                .indices
+                .extend([0, 1, 2, 0, 2, 3].map(|i| start + i));
+            icon_mesh.quads.push(glyph.item);
+            continue;
+        }
+
+        let font = &graphics.fonts[&glyph.font];
+        let (atlas, raster_size) = match style.sdf {
+            Some(_) => (&mut graphics.sdf_atlas, font.raster_size(SDF_SIZE)),
+            None => (&mut graphics.glyph_atlas, font.raster_size(item.size)),
+        };
+        let scale = item.size / raster_size;
+        let key = GlyphKey {
+            font: glyph.font,
+            index: glyph.index,
+            size: raster_size.to_bits(),
+        };
+        let atlas_glyph = atlas.glyph(
+            &graphics.device,
+            &graphics.queue,
+            &mut graphics.texture_manager,
+            &graphics.fonts,
+            key,
+        );
+        if atlas_glyph.size == UVec2::ZERO {
+            continue;
+        }
+
+        let min = glyph.position + glyph.offset + atlas_glyph.offset * scale;
+        let max = min + atlas_glyph.size.as_vec2() * scale;
+        let uv_min = atlas_glyph.position.as_vec2();
+        let uv_max = uv_min + atlas_glyph.size.as_vec2();
+
+        let start = mesh.vertices.len() as u32;
+        mesh.vertices.extend(
+            [
+                (Vec2::new(min.x, min.y), Vec2::new(uv_min.x, uv_max.y)),
+                (Vec2::new(max.x, min.y), Vec2::new(uv_max.x, uv_max.y)),
+                (Vec2::new(max.x, max.y), Vec2::new(uv_max.x, uv_min.y)),
+                (Vec2::new(min.x, max.y), Vec2::new(uv_min.x, uv_min.y)),
+            ]
+            .map(|(position, tex_coords)| TextVertex {
+                position: position.extend(0.0).into(),
+                tex_coords: tex_coords.into(),
+                color: item.color,
+                rect: [uv_min.x, uv_min.y, uv_max.x, uv_max.y],
+            }),
+        );
+        mesh.indices.extend([0, 1, 2, 0, 2, 3].map(|i| start + i));
+        mesh.quads.push(glyph.item);
+    }
+
+    mesh
+}
+
+/// Lay out plain text and find the area of its lines and characters.
+pub(crate) fn measure(
+    fonts: &IndexMap<FontID, Font>,
+    font: FontID,
+    text: &str,
+    size: f32,
+    max_width: Option<f32>,
+) -> TextMetrics {
+    let style = TextStyle {
+        max_width,
+        ..TextStyle::new(size, Color::WHITE)
+    };
+    let items = plain_items(text, font, &style);
+    let layout = layout(fonts, &items, &style);
+    // The byte every item starts at, followed by the end of the text.
+    let bytes = text
+        .char_indices()
+        .map(|(byte, _)| byte)
+        .chain(std::iter::once(text.len()))
+        .collect::<Vec<_>>();
+
+    let mut lines = Vec::new();
+    let mut glyphs = Vec::new();
+    for (i, line) in layout.lines.iter().enumerate() {
+        let rect = Rect::new(
+            Vec2::new(0.0, line.top - line.height),
+            Vec2::new(line.width, line.top),
+        );
+        lines.push(TextLine {
+            range: bytes[line.items.start]..bytes[line.items.end],
+            rect,
+            baseline: line.baseline,
+        });
+
+        // Combine the glyphs made from the same characters, which are in clusters
+        // starting at the first of them.
+        let mut clusters: Vec<(usize, f32, f32, bool)> = Vec::new();
+        for glyph in &line.glyphs {
+            let (min, max) = (glyph.position.x, glyph.position.x + glyph.advance);
+            match clusters.iter_mut().find(|cluster| cluster.0 == glyph.item) {
+                Some(cluster) => {
+                    cluster.1 = cluster.1.min(min);
+                    cluster.2 = cluster.2.max(max);
+                }
+                None => clusters.push((glyph.item, min, max, glyph.level % 2 == 1)),
+            }
+        }
+        clusters.sort_by_key(|cluster| cluster.0);
+
+        for (j, &(start, min, max, rtl)) in clusters.iter().enumerate() {
+            let end = clusters.get(j + 1).map_or(line.items.end, |next| next.0);
+            let width = (max - min) / (end - start) as f32;
+            for item in start..end {
+                let step = (item - start) as f32;
+                let x = match rtl {
+                    true => max - (step + 1.0) * width,
+                    false => min + step * width,
+                };
+                glyphs.push(GlyphRect {
+                    range: bytes[item]..bytes[item + 1],
+                    line: i,
+                    rect: Rect::new(Vec2::new(x, rect.min.y), Vec2::new(x + width, rect.max.y)),
+                    rtl,
+                });
+            }
+        }
+    }
+
+    TextMetrics {
+        size: layout.size,
+        lines,
+        glyphs,
+    }
+}
+
+/// Draw `text` into an image, with the top left of the text in the top left corner.
+pub(crate) fn render_to_image(
+    fonts: &IndexMap<FontID, Font>,
+    font: FontID,
+    text: &str,
+    style: &TextStyle,
+) -> RgbaImage {
+    let style = TextStyle {
+        align: Align::Left,
+        ..*style
+    };
+    let items = plain_items(text, font, &style);
+    let layout = layout(fonts, &items, &style);
+    let mut image = RgbaImage::new(
+        layout.size.x.ceil().max(1.0) as u32,
+        layout.size.y.ceil().max(1.0) as u32,
+    );
+    let color = [style.color.r, style.color.g, style.color.b].map(|c| (c * 255.0) as u8);
+
+    for glyph in layout.lines.iter().flat_map(|line| &line.glyphs) {
+        let font = &fonts[&glyph.font];
+        let scale = style.size / font.raster_size(style.size);
+        let glyph_image = font.rasterize(glyph.index, font.raster_size(style.size));
+        let size = (glyph_image.size.as_vec2() * scale).round().as_uvec2();
+        let min = glyph.position + glyph.offset + glyph_image.offset * scale;
+        let left = min.x.round() as i32;
+        let top = (-min.y - size.y as f32).round() as i32;
+
+        // Bitmap fonts are scaled up with nearest filtering.
+        for (x, y) in (0..size.y).flat_map(|y| (0..size.x).map(move |x| (x, y))) {
+            let source = (UVec2::new(x, y).as_vec2() / scale)
+                .as_uvec2()
+                .min(glyph_image.size - 1);
+            let alpha = glyph_image.coverage[(source.y * glyph_image.size.x + source.x) as usize];
+            let (x, y) = (left + x as i32, top + y as i32);
+            if x >= 0 && y >= 0 && (x as u32) < image.width() && (y as u32) < image.height() {
+                let pixel = image.get_pixel_mut(x as u32, y as u32);
+                if alpha > pixel[3] {
+                    *pixel = image::Rgba([color[0], color[1], color[2], alpha]);
+                }
+            }
+        }
+    }
+
+    image
+}
+
+/// Turn a glyph's coverage into a signed distance field reaching `SDF_SPREAD` pixels
+/// either side of its edges, where 0.5 is on the edge and higher values are inside.
+fn distance_field(image: &GlyphImage) -> GlyphImage {
+    let spread = SDF_SPREAD as i32;
+    let size = image.size + 2 * SDF_SPREAD;
+    let inside = |x: i32, y: i32| {
+        let (x, y) = (x - spread, y - spread);
+        x >= 0
+            && y >= 0
+            && (x as u32) < image.size.x
+            && (y as u32) < image.size.y
+            && image.coverage[(y as u32 * image.size.x + x as u32) as usize] >= 128
+    };
+
+    let mut coverage = Vec::with_capacity((size.x * size.y) as usize);
+    for y in 0..size.y as i32 {
+        for x in 0..size.x as i32 {
+            // Find the nearest pixel on the other side of the edge.
+            let this = inside(x, y);
+            let mut nearest = (spread * spread) as f32;
+            for dy in -spread..=spread {
+                for dx in -spread..=spread {
+                    if inside(x + dx, y + dy) != this {
+                        nearest = nearest.min((dx * dx + dy * dy) as f32);
+                    }
+                }
+            }
+
+            let distance = (nearest.sqrt() - 0.5).max(0.0);
+            let signed = if this { distance } else { -distance };
+            let value = 0.5 + signed / (2 * SDF_SPREAD) as f32;
+            coverage.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+
+    GlyphImage {
+        offset: image.offset - SDF_SPREAD as f32,
+        size,
+        coverage,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct GlyphKey {
+    pub font: FontID,
+    pub index: u16,
+    /// The bits of the font size, which can't be hashed as a float.
+    pub size: u32,
+}
+
+/// Where a glyph is stored in the glyph atlas.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AtlasGlyph {
+    /// The top left of the glyph in the atlas, in pixels.
+    pub position: UVec2,
+    pub size: UVec2,
+    /// The offset from the glyph's origin to the bottom left of its image.
+    pub offset: Vec2,
+}
+
+/// The largest width and height the glyph atlas can grow to.
+fn max_size(device: &Device) -> u32 {
+    device.limits().max_texture_dimension_2d
+}
+
+/// A row of glyphs in the atlas, which are packed from left to right.
+struct Shelf {
+    y: u32,
+    height: u32,
+    x: u32,
+}
+
+/// A texture that every glyph is drawn into the first time it is used, which grows when
+/// it fills up.
+pub(crate) struct GlyphAtlas {
+    pub id: TextureID,
+    /// The same texture as `id`, but with nearest filtering for bitmap fonts.
+    pub nearest_id: TextureID,
+    /// If this is true, glyphs are stored as signed distance fields.
+    sdf: bool,
+    texture: wgpu::Texture,
+    size: u32,
+    glyphs: HashMap<GlyphKey, AtlasGlyph>,
+    shelves: Vec<Shelf>,
+    /// Counts the times the atlas has been cleared, so text knows to rebuild.
+    generation: u32,
+}
+
+impl GlyphAtlas {
+    /// The space left around every glyph, so that filtering doesn't pick up neighbours.
+    const PADDING: u32 = 1;
+
+    pub fn new(device: &Device, texture_manager: &mut TextureManager, sdf: bool) -> Self {
+        let size = 256;
+        let texture = Self::make_texture(device, size);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let linear = texture_manager.make_bind_group(device, &view, Filter::Linear);
+        let nearest = texture_manager.make_bind_group(device, &view, Filter::Nearest);
+
+        Self {
+            id: texture_manager.insert_bind_group(linear, false),
+            nearest_id: texture_manager.insert_bind_group(nearest, false),
+            sdf,
+            texture,
+            size,
+            glyphs: HashMap::new(),
+            shelves: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    fn make_texture(device: &Device, size: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("glyph_atlas"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+        })
+    }
+
+    /// The atlas texture to draw glyphs with when using `filter`.
+    pub fn texture_id(&self, filter: Filter) -> TextureID {
+        match filter {
+            Filter::Linear => self.id,
+            Filter::Nearest => self.nearest_id,
+        }
+    }
+
+    /// Forget every glyph, which is needed when the fonts they came from are removed.
+    pub fn clear(&mut self) {
+        self.glyphs.clear();
+        self.shelves.clear();
+        self.generation += 1;
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Find a glyph in the atlas, drawing it into the atlas if it isn't there yet.
+    /// Glyphs too big to fit in the largest atlas are left empty, and aren't drawn.
+    pub fn glyph(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        texture_manager: &mut TextureManager,
+        fonts: &IndexMap<FontID, Font>,
+        key: GlyphKey,
+    ) -> AtlasGlyph {
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return *glyph;
+        }
+
+        // Huge glyphs are checked before they are drawn, as just drawing them can run out
+        // of memory.
+        let font = &fonts[&key.font];
+        let spread = if self.sdf { 2 * SDF_SPREAD } else { 0 };
+        let fits = |size: UVec2| size.max_element() + spread + Self::PADDING <= max_size(device);
+        let mut image = match fits(font.glyph_size(key.index, f32::from_bits(key.size))) {
+            true => font.rasterize(key.index, f32::from_bits(key.size)),
+            false => GlyphImage {
+                offset: Vec2::ZERO,
+                size: UVec2::ZERO,
+                coverage: Vec::new(),
+            },
+        };
+        if self.sdf && image.size != UVec2::ZERO {
+            image = distance_field(&image);
+        }
+        let size = image.size;
+        let position = match size == UVec2::ZERO {
+            true => Some(UVec2::ZERO),
+            false => self.allocate(device, queue, texture_manager, size),
+        };
+        let (position, size) = match position {
+            Some(position) => (position, size),
+            None => (UVec2::ZERO, UVec2::ZERO),
+        };
+
+        if size != UVec2::ZERO {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: position.x,
+                        y: position.y,
+                        z: 0,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &image.coverage,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(size.x),
+                    rows_per_image: std::num::NonZeroU32::new(size.y),
+                },
+                wgpu::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let glyph = AtlasGlyph {
+            position,
+            size,
+            offset: image.offset,
+        };
+        self.glyphs.insert(key, glyph);
+        glyph
+    }
+
+    /// Find space for an image of `size` pixels, growing the atlas if it doesn't fit.
+    /// Returns `None` if it doesn't fit in an empty atlas of the largest size.
+    fn allocate(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        texture_manager: &mut TextureManager,
+        size: UVec2,
+    ) -> Option<UVec2> {
+        let padded = size + Self::PADDING;
+        loop {
+            // Use the shortest shelf that the glyph fits on, or start a new one.
+            let shelf = self
+                .shelves
+                .iter_mut()
+                .filter(|shelf| shelf.height >= padded.y && shelf.x + padded.x <= self.size)
+                .min_by_key(|shelf| shelf.height);
+            if let Some(shelf) = shelf {
+                let position = UVec2::new(shelf.x, shelf.y);
+                shelf.x += padded.x;
+                return Some(position);
+            }
+
+            let top = self
+                .shelves
+                .last()
+                .map_or(0, |shelf| shelf.y + shelf.height);
+            if top + padded.y <= self.size && padded.x <= self.size {
+                self.shelves.push(Shelf {
+                    y: top,
+                    height: padded.y,
+                    x: 0,
+                });
+                continue;
+            }
+
+            // Once the atlas is as big as it can be, every glyph is thrown away to make
+            // room, and text using them is rebuilt.
+            if !self.grow(device, queue, texture_manager) {
+                if self.shelves.is_empty() {
+                    return None;
+                }
+                self.clear();
+            }
+        }
+    }
+
+    /// Double the size of the atlas, keeping the glyphs where they are. Returns false if
+    /// the atlas is already as big as a texture can be.
+    fn grow(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        texture_manager: &mut TextureManager,
+    ) -> bool {
+        let size = self.size * 2;
+        if size > max_size(device) {
+            return false;
+        }
+
+        let texture = Self::make_texture(device, size);
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_texture(
+            self.texture.as_image_copy(),
+            texture.as_image_copy(),
+            wgpu::Extent3d {
+                width: self.size,
+                height: self.size,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        for filter in [Filter::Linear, Filter::Nearest] {
+            let bind_group = texture_manager.make_bind_group(device, &view, filter);
+            texture_manager.set_bind_group(self.texture_id(filter), bind_group);
+        }
+        self.texture = texture;
+        self.size = size;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A regular font and a bold one, made from grids of 8 pixel cells so every
+    /// character is 8 pixels wide at a size of 8.
+    fn fonts() -> IndexMap<FontID, Font> {
+        let image = DynamicImage::new_rgba8(64, 64);
+        let font = || Font::from_grid(&image, UVec2::splat(8), "abcdefgh ").unwrap();
+        let mut fonts = IndexMap::new();
+        fonts.insert(0, font());
+        fonts.insert(1, font());
+        fonts
+    }
+
+    fn style() -> TextStyle {
+        TextStyle::new(8.0, Color::WHITE)
+    }
+
+    fn characters(items: &[TextItem]) -> String {
+        items.iter().map(|item| item.character).collect()
+    }
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(parse_color("red"), Some([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(parse_color("#00ff00"), Some([0.0, 1.0, 0.0, 1.0]));
+        assert_eq!(parse_color("#0000ff00"), Some([0.0, 0.0, 1.0, 0.0]));
+        assert_eq!(parse_color("#00ff0"), None);
+        assert_eq!(parse_color("#gg0000"), None);
+        assert_eq!(parse_color("purple"), None);
+    }
+
+    #[test]
+    fn markup_nesting() {
+        let markup = Markup::new().with_bold(1);
+        let items = markup.parse(
+            "a[color=red]b[size=16]c[/size][b]d[/b][/color]e",
+            0,
+            &style(),
+            &fonts(),
+        );
+        assert_eq!(characters(&items), "abcde");
+        let sizes: Vec<_> = items.iter().map(|item| item.size).collect();
+        assert_eq!(sizes, [8.0, 8.0, 16.0, 8.0, 8.0]);
+        let red: Vec<_> = items.iter().map(|item| item.color[1] == 0.0).collect();
+        assert_eq!(red, [false, true, true, true, false]);
+        let fonts: Vec<_> = items.iter().map(|item| item.font).collect();
+        assert_eq!(fonts, [0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn markup_unknown_tags() {
+        let parse = |text| characters(&Markup::new().parse(text, 0, &style(), &fonts()));
+        assert_eq!(parse("[[b]"), "[b]");
+        assert_eq!(parse("[wave]a[/b][/color]"), "[wave]a[/b][/color]");
+        assert_eq!(parse("[color=nope]a"), "[color=nope]a");
+        assert_eq!(
+            parse("[font=99]a[size=0]b[size=inf]c"),
+            "[font=99]a[size=0]b[size=inf]c"
+        );
+        assert_eq!(parse("[font=1]a[/font]"), "a");
+        assert_eq!(parse("[b"), "[b");
+    }
+
+    #[test]
+    fn wrapping() {
+        let metrics = measure(&fonts(), 0, "abc abc abc", 8.0, Some(60.0));
+        assert_eq!(metrics.lines.len(), 2);
+        // Lines keep the spaces they were wrapped after, but aren't as wide as them.
+        assert_eq!(metrics.lines[0].range, 0..8);
+        assert_eq!(metrics.lines[0].rect.max.x, 56.0);
+        assert_eq!(metrics.lines[1].range, 8..11);
+        assert_eq!(metrics.lines[1].rect.max.x, 24.0);
+        assert_eq!(metrics.size, Vec2::new(56.0, 16.0));
+
+        let metrics = measure(&fonts(), 0, "ab\n\nc", 8.0, None);
+        let ranges: Vec<_> = metrics
+            .lines
+            .iter()
+            .map(|line| line.range.clone())
+            .collect();
+        assert_eq!(ranges, [0..2, 3..3, 4..5]);
+        assert_eq!(metrics.glyphs.len(), 3);
+    }
+
+    #[test]
+    fn alignment() {
+        let first_x = |align, text| {
+            let style = style().with_align(align).with_max_width(60.0);
+            let layout = layout(&fonts(), &plain_items(text, 0, &style), &style);
+            layout.lines[0]
+                .glyphs
+                .iter()
+                .map(|glyph| glyph.position.x)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(first_x(Align::Left, "ab"), [0.0, 8.0]);
+        assert_eq!(first_x(Align::Center, "ab"), [-8.0, 0.0]);
+        assert_eq!(first_x(Align::Right, "ab"), [-16.0, -8.0]);
+        // Wrapped lines are stretched to the width by widening their spaces.
+        assert_eq!(first_x(Align::Justify, "abc abc abc")[4], 36.0);
+    }
+
+    #[test]
+    fn caret_and_hit_test() {
+        let text = "abc abc abc";
+        let metrics = measure(&fonts(), 0, text, 8.0, Some(60.0));
+        assert_eq!(metrics.caret_rect(1).min, Vec2::new(8.0, -8.0));
+        assert_eq!(metrics.caret_rect(8).min, Vec2::new(0.0, -16.0));
+        assert_eq!(metrics.caret_rect(11).min, Vec2::new(24.0, -16.0));
+        for byte in 0..=text.len() {
+            let caret = metrics.caret_rect(byte);
+            assert_eq!(metrics.hit_test(caret.center()), byte);
+        }
+
+        // Points past the text go to the nearest line and the nearest side of it.
+        assert_eq!(metrics.hit_test(Vec2::new(-10.0, 10.0)), 0);
+        assert_eq!(metrics.hit_test(Vec2::new(100.0, -100.0)), 11);
+        assert_eq!(metrics.hit_test(Vec2::new(13.0, -4.0)), 2);
+    }
+}
@@ -0,0 +1,66 @@
+struct Transform {
+    @location(2) data0: vec4<f32>,
+    @location(3) data1: vec4<f32>,
+    @location(4) data2: vec4<f32>,
+    @location(5) data3: vec4<f32>,
+    // Multiplies the color of the sprite.
+    @location(9) tint: vec4<f32>,
+}
+
+@group(0)@binding(0)
+var<uniform> camera: mat4x4<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(6) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vertex_main(vertex: VertexInput, transform: Transform) -> VertexOutput {
+    let transform_matrix = mat4x4<f32>(
+        transform.data0,
+        transform.data1,
+        transform.data2,
+        transform.data3,
+    );
+
+    var output: VertexOutput;
+    output.clip_position = camera * transform_matrix * vec4<f32>(vertex.position, 1.0);
+    output.tex_coords = vertex.tex_coords;
+    output.color = vertex.color * transform.tint;
+    return output;
+}
+
+@group(1)@binding(0)
+var texture: texture_2d<f32>;
+@group(1)@binding(1)
+var texture_sampler: sampler;
+
+// The texture coordinates are in pixels, so that they stay correct when the glyph
+// atlas grows.
+fn coverage(tex_coords: vec2<f32>) -> f32 {
+    let size = vec2<f32>(textureDimensions(texture));
+    return textureSample(texture, texture_sampler, tex_coords / size).r;
+}
+
+@fragment
+fn fragment_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(vertex.color.rgb, vertex.color.a * coverage(vertex.tex_coords));
+}
+
+// Used when drawing the text as a mask, where only its shape matters.
+@fragment
+fn fragment_mask(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let alpha = coverage(vertex.tex_coords);
+    if (alpha < 0.5) {
+        discard;
+    }
+    return vec4<f32>(vertex.color.rgb, alpha);
+}
@@ -0,0 +1,119 @@
+struct Transform {
+    @location(2) data0: vec4<f32>,
+    @location(3) data1: vec4<f32>,
+    @location(4) data2: vec4<f32>,
+    @location(5) data3: vec4<f32>,
+    // Multiplies the color of the sprite.
+    @location(9) tint: vec4<f32>,
+}
+
+@group(0)@binding(0)
+var<uniform> camera: mat4x4<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(6) color: vec4<f32>,
+    // The corners of the glyph's cell in the atlas, in pixels.
+    @location(10) rect: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) rect: vec4<f32>,
+}
+
+@vertex
+fn vertex_main(vertex: VertexInput, transform: Transform) -> VertexOutput {
+    let transform_matrix = mat4x4<f32>(
+        transform.data0,
+        transform.data1,
+        transform.data2,
+        transform.data3,
+    );
+
+    var output: VertexOutput;
+    output.clip_position = camera * transform_matrix * vec4<f32>(vertex.position, 1.0);
+    output.tex_coords = vertex.tex_coords;
+    output.color = vertex.color * transform.tint;
+    output.rect = vertex.rect;
+    return output;
+}
+
+@group(1)@binding(0)
+var texture: texture_2d<f32>;
+@group(1)@binding(1)
+var texture_sampler: sampler;
+
+// Widths are in units of the distance field, where 0.5 is the edge of the glyph and a
+// step of 0.5 is the full spread of the field. The shadow offset is in atlas pixels.
+struct Style {
+    outline_color: vec4<f32>,
+    shadow_color: vec4<f32>,
+    glow_color: vec4<f32>,
+    shadow_offset: vec2<f32>,
+    outline_width: f32,
+    glow_width: f32,
+    shadow_softness: f32,
+}
+
+@group(2)@binding(0)
+var<uniform> style: Style;
+
+fn field(tex_coords: vec2<f32>) -> f32 {
+    let size = vec2<f32>(textureDimensions(texture));
+    return textureSample(texture, texture_sampler, tex_coords / size).r;
+}
+
+// Put `top` over `bottom`, neither of which is premultiplied.
+fn over(top: vec4<f32>, bottom: vec4<f32>) -> vec4<f32> {
+    let alpha = top.a + bottom.a * (1.0 - top.a);
+    if (alpha <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    let color = (top.rgb * top.a + bottom.rgb * bottom.a * (1.0 - top.a)) / alpha;
+    return vec4<f32>(color, alpha);
+}
+
+@fragment
+fn fragment_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let d = field(vertex.tex_coords);
+    // The width of a pixel in distance units, which keeps the edges sharp at any scale.
+    let width = max(fwidth(d), 0.0001);
+    // The shadow stays inside the glyph's cell, where the field past its edge is empty,
+    // rather than reaching into the glyphs next to it.
+    let shadow_coords = clamp(
+        vertex.tex_coords - style.shadow_offset,
+        vertex.rect.xy + 0.5,
+        vertex.rect.zw - 0.5,
+    );
+    let shadow_d = field(shadow_coords);
+
+    let fill = smoothstep(0.5 - width, 0.5 + width, d);
+    let outline_edge = 0.5 - style.outline_width;
+    let outline = smoothstep(outline_edge - width, outline_edge + width, d);
+    let glow = smoothstep(outline_edge - max(style.glow_width, width), outline_edge, d);
+    let shadow_softness = max(style.shadow_softness, width);
+    let shadow = smoothstep(
+        outline_edge - shadow_softness,
+        outline_edge + shadow_softness,
+        shadow_d,
+    );
+
+    var color = vec4<f32>(style.shadow_color.rgb, style.shadow_color.a * shadow);
+    color = over(vec4<f32>(style.glow_color.rgb, style.glow_color.a * glow), color);
+    color = over(vec4<f32>(style.outline_color.rgb, style.outline_color.a * outline), color);
+    color = over(vec4<f32>(vertex.color.rgb, vertex.color.a * fill), color);
+    return color;
+}
+
+// Used when drawing the text as a mask, where only its shape matters.
+@fragment
+fn fragment_mask(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    if (field(vertex.tex_coords) < 0.5 - style.outline_width) {
+        discard;
+    }
+    return vertex.color;
+}
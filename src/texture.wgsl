@@ -3,8 +3,15 @@ struct Transform {
     @location(3) data1: vec4<f32>,
     @location(4) data2: vec4<f32>,
     @location(5) data3: vec4<f32>,
+    // The offset of the texture coordinates followed by their scale.
+    @location(7) uv: vec4<f32>,
+    // Multiplies the color of the sprite.
+    @location(9) tint: vec4<f32>,
 }
 
+@group(0)@binding(0)
+var<uniform> camera: mat4x4<f32>;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
@@ -13,6 +20,7 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) tint: vec4<f32>,
 }
 
 @vertex
@@ -25,17 +33,28 @@ fn vertex_main(vertex: VertexInput, transform: Transform) -> VertexOutput {
     );
 
     var output: VertexOutput;
-    output.clip_position = transform_matrix * vec4<f32>(vertex.position, 1.0);
-    output.tex_coords = vertex.tex_coords;
+    output.clip_position = camera * transform_matrix * vec4<f32>(vertex.position, 1.0);
+    output.tex_coords = vertex.tex_coords * transform.uv.zw + transform.uv.xy;
+    output.tint = transform.tint;
     return output;
 }
 
-@group(0)@binding(0)
+@group(1)@binding(0)
 var texture: texture_2d<f32>;
-@group(0)@binding(1)
+@group(1)@binding(1)
 var texture_sampler: sampler;
 
 @fragment
 fn fragment_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(texture, texture_sampler, vertex.tex_coords);
+    return textureSample(texture, texture_sampler, vertex.tex_coords) * vertex.tint;
+}
+
+// Used when drawing the sprite as a mask, where only its shape matters.
+@fragment
+fn fragment_mask(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(texture, texture_sampler, vertex.tex_coords) * vertex.tint;
+    if (color.a < 0.5) {
+        discard;
+    }
+    return color;
 }